@@ -40,6 +40,7 @@ fn main() {
   );
 
   serve_process.kill().expect("Failed to stop npx serve");
+  serve_process.wait().expect("Failed to wait for npx serve");
 
   let mut swerve_process = create_process(&["swerve", "--port", "8080"]);
 
@@ -55,6 +56,7 @@ fn main() {
   );
 
   swerve_process.kill().expect("Failed to stop swerve");
+  swerve_process.wait().expect("Failed to wait for swerve");
 
   let serve_micros_flt = micros_to_f64(serve_duration.as_micros());
   let swerve_micros_flt = micros_to_f64(swerve_duration.as_micros());
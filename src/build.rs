@@ -0,0 +1,95 @@
+use std::{
+  io::{Read, Write},
+  process::{Command, Stdio},
+  time::Instant,
+};
+
+use crate::{error, hotreload::BuildStatus, log, success};
+
+/// Run a build command through the shell and wait for it to finish.
+///
+/// Output goes to the terminal like it normally would, with stderr also being captured so
+/// failed builds can be reported to the browser.
+pub fn run(command: &str) -> BuildStatus {
+  log!("Running build: {}", command);
+
+  let start = Instant::now();
+  let child = shell(command)
+    .stdin(Stdio::null())
+    .stdout(Stdio::inherit())
+    .stderr(Stdio::piped())
+    .spawn();
+
+  let mut child = match child {
+    Ok(child) => child,
+    Err(e) => {
+      error!("Failed to run build command: {}", e);
+      return BuildStatus {
+        success: false,
+        exit_code: None,
+        stderr: e.to_string(),
+      };
+    }
+  };
+
+  // Pass stderr through as it comes in, rather than all at once when the build is done
+  let mut stderr = Vec::new();
+
+  if let Some(mut pipe) = child.stderr.take() {
+    let mut buf = [0; 4096];
+
+    while let Ok(read @ 1..) = pipe.read(&mut buf) {
+      std::io::stderr()
+        .write_all(&buf[..read])
+        .unwrap_or_default();
+      stderr.extend_from_slice(&buf[..read]);
+    }
+  }
+
+  let status = child.wait();
+  let stderr = String::from_utf8_lossy(&stderr).to_string();
+
+  match status {
+    Ok(status) if status.success() => {
+      success!("Build finished in {:?}", start.elapsed());
+      BuildStatus {
+        success: true,
+        exit_code: status.code(),
+        stderr,
+      }
+    }
+    Ok(status) => {
+      error!("Build failed ({}) after {:?}", status, start.elapsed());
+      BuildStatus {
+        success: false,
+        exit_code: status.code(),
+        stderr,
+      }
+    }
+    Err(e) => {
+      error!("Failed to wait for build command: {}", e);
+      BuildStatus {
+        success: false,
+        exit_code: None,
+        stderr,
+      }
+    }
+  }
+}
+
+#[cfg(target_os = "windows")]
+fn shell(command: &str) -> Command {
+  use std::os::windows::process::CommandExt;
+
+  let mut shell = Command::new("cmd");
+  // cmd does its own parsing, so the command is passed as-is rather than quoted as a single argument
+  shell.arg("/C").raw_arg(command);
+  shell
+}
+
+#[cfg(not(target_os = "windows"))]
+fn shell(command: &str) -> Command {
+  let mut shell = Command::new("sh");
+  shell.arg("-c").arg(command);
+  shell
+}
@@ -0,0 +1,165 @@
+use std::{
+  fs::Metadata,
+  hash::{DefaultHasher, Hash, Hasher},
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+
+static HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// Entity tag for a file on disk, derived from its modification time and size.
+pub fn file_etag(meta: &Metadata) -> String {
+  let mtime = meta
+    .modified()
+    .ok()
+    .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+    .unwrap_or_default();
+
+  format!("\"{:x}-{:x}\"", mtime.as_nanos(), meta.len())
+}
+
+/// Entity tag for generated content (eg. directory listings), derived from a hash of the content.
+pub fn content_etag(content: &[u8]) -> String {
+  let mut hasher = DefaultHasher::new();
+  content.hash(&mut hasher);
+
+  format!("\"{:x}\"", hasher.finish())
+}
+
+pub fn http_date(time: SystemTime) -> String {
+  let time: DateTime<Utc> = time.into();
+  time.format(HTTP_DATE_FORMAT).to_string()
+}
+
+fn parse_http_date(date: &str) -> Option<DateTime<Utc>> {
+  NaiveDateTime::parse_from_str(date.trim(), HTTP_DATE_FORMAT)
+    .ok()
+    .map(|date| date.and_utc())
+}
+
+/// Whether the client's cached copy is still fresh, meaning we can respond with 304.
+///
+/// If-None-Match takes precedence over If-Modified-Since when both are present, as per RFC 9110.
+pub fn is_not_modified(
+  if_none_match: Option<&str>,
+  if_modified_since: Option<&str>,
+  etag: &str,
+  last_modified: Option<SystemTime>,
+) -> bool {
+  if let Some(if_none_match) = if_none_match {
+    return if_none_match.split(',').any(|tag| {
+      let tag = tag.trim();
+      // Weak comparison, so W/ prefixes are ignored
+      tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag
+    });
+  }
+
+  match (if_modified_since.and_then(parse_http_date), last_modified) {
+    (Some(since), Some(last_modified)) => {
+      let last_modified: DateTime<Utc> = last_modified.into();
+      // HTTP dates only have second precision
+      last_modified.timestamp() <= since.timestamp()
+    }
+    _ => false,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::Duration;
+
+  static ETAG: &str = "\"abc-1\"";
+
+  fn time(secs: u64, millis: u64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(secs) + Duration::from_millis(millis)
+  }
+
+  #[test]
+  fn matches_entity_tags() {
+    assert!(is_not_modified(Some(ETAG), None, ETAG, None));
+    assert!(is_not_modified(
+      Some("\"other\", \"abc-1\""),
+      None,
+      ETAG,
+      None
+    ));
+    assert!(is_not_modified(Some("*"), None, ETAG, None));
+    assert!(!is_not_modified(Some("\"other\""), None, ETAG, None));
+  }
+
+  #[test]
+  fn compares_entity_tags_weakly() {
+    assert!(is_not_modified(Some("W/\"abc-1\""), None, ETAG, None));
+    assert!(is_not_modified(
+      Some("\"other\", W/\"abc-1\""),
+      None,
+      ETAG,
+      None
+    ));
+  }
+
+  #[test]
+  fn prefers_if_none_match_over_if_modified_since() {
+    let modified = time(1_700_000_000, 0);
+    let date = http_date(modified);
+
+    assert!(!is_not_modified(
+      Some("\"other\""),
+      Some(&date),
+      ETAG,
+      Some(modified)
+    ));
+    assert!(is_not_modified(
+      Some(ETAG),
+      Some("Thu, 01 Jan 1970 00:00:00 GMT"),
+      ETAG,
+      Some(modified)
+    ));
+  }
+
+  #[test]
+  fn compares_dates_to_the_second() {
+    let date = http_date(time(1_700_000_000, 0));
+
+    // Sub-second precision is lost in the header, so it mustn't count as newer
+    assert!(is_not_modified(
+      None,
+      Some(&date),
+      ETAG,
+      Some(time(1_700_000_000, 900))
+    ));
+    assert!(is_not_modified(
+      None,
+      Some(&date),
+      ETAG,
+      Some(time(1_699_999_999, 0))
+    ));
+    assert!(!is_not_modified(
+      None,
+      Some(&date),
+      ETAG,
+      Some(time(1_700_000_001, 0))
+    ));
+  }
+
+  #[test]
+  fn ignores_missing_or_invalid_dates() {
+    let modified = time(1_700_000_000, 0);
+
+    assert!(!is_not_modified(None, None, ETAG, Some(modified)));
+    assert!(!is_not_modified(
+      None,
+      Some("yesterday"),
+      ETAG,
+      Some(modified)
+    ));
+    assert!(!is_not_modified(
+      None,
+      Some(&http_date(modified)),
+      ETAG,
+      None
+    ));
+  }
+}
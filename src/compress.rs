@@ -0,0 +1,203 @@
+#[cfg(feature = "compression")]
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[cfg(feature = "compression")]
+use flate2::{write::GzEncoder, Compression};
+#[cfg(feature = "compression")]
+use mime_guess::{mime, Mime};
+
+// Compressing tiny files isn't worth the CPU time (and can even make them bigger)
+#[cfg(feature = "compression")]
+pub const MIN_COMPRESS_SIZE: usize = 1024;
+
+// Compression levels picked for speed, since we compress on every request
+#[cfg(feature = "compression")]
+const BROTLI_QUALITY: u32 = 5;
+#[cfg(feature = "compression")]
+const BROTLI_WINDOW: u32 = 22;
+#[cfg(feature = "compression")]
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Encoding {
+  Brotli,
+  Zstd,
+  Gzip,
+}
+
+impl Encoding {
+  // In order of preference when the client has no preference of its own
+  pub const ALL: [Encoding; 3] = [Encoding::Brotli, Encoding::Zstd, Encoding::Gzip];
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      Encoding::Brotli => "br",
+      Encoding::Zstd => "zstd",
+      Encoding::Gzip => "gzip",
+    }
+  }
+
+  /// File extension used for precompressed sidecar files (eg. app.js.br)
+  pub fn extension(&self) -> &'static str {
+    match self {
+      Encoding::Brotli => "br",
+      Encoding::Zstd => "zst",
+      Encoding::Gzip => "gz",
+    }
+  }
+
+  /// Entity tags must differ between encodings of the same file, so suffix the encoding name.
+  pub fn etag(&self, etag: &str) -> String {
+    format!("{}-{}\"", etag.trim_end_matches('"'), self.name())
+  }
+}
+
+/// Whether content of this type is worth compressing. Most binary formats (images, video, archives) are already compressed.
+#[cfg(feature = "compression")]
+pub fn is_compressible(mime: &Mime) -> bool {
+  if mime.type_() == mime::TEXT {
+    return true;
+  }
+
+  // Things like application/ld+json or image/svg+xml
+  if matches!(mime.suffix(), Some(mime::JSON) | Some(mime::XML)) {
+    return true;
+  }
+
+  matches!(
+    (mime.type_().as_str(), mime.subtype().as_str()),
+    ("application", "javascript")
+      | ("application", "json")
+      | ("application", "xml")
+      | ("application", "wasm")
+      | ("application", "x-javascript")
+      | ("application", "x-sh")
+      | ("application", "toml")
+      | ("application", "yaml")
+      | ("application", "x-yaml")
+      | ("image", "svg+xml")
+      | ("image", "x-icon")
+      | ("image", "vnd.microsoft.icon")
+      | ("image", "bmp")
+      | ("font", "ttf")
+      | ("font", "otf")
+  )
+}
+
+/// Pick one of the available encodings based on the client's `Accept-Encoding` header, respecting q-values.
+pub fn negotiate(accept_encoding: &str, available: &[Encoding]) -> Option<Encoding> {
+  let mut best: Option<(Encoding, f32)> = None;
+
+  for &encoding in available {
+    let mut quality = None;
+
+    for entry in accept_encoding.split(',') {
+      let mut parts = entry.split(';');
+      let name = parts.next().unwrap_or_default().trim();
+      let q = parts
+        .find_map(|param| param.trim().strip_prefix("q="))
+        .and_then(|q| q.trim().parse::<f32>().ok())
+        .unwrap_or(1.0);
+
+      if name.eq_ignore_ascii_case(encoding.name()) {
+        quality = Some(q);
+        break;
+      }
+
+      // Wildcards only apply to encodings not explicitly listed
+      if name == "*" {
+        quality.get_or_insert(q);
+      }
+    }
+
+    match quality {
+      Some(q) if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) => {
+        best = Some((encoding, q));
+      }
+      _ => {}
+    }
+  }
+
+  best.map(|(encoding, _)| encoding)
+}
+
+/// Look for a precompressed version of the file (eg. app.js.br next to app.js) the client can accept.
+/// Sidecars are only considered if `is_servable` allows serving them, same as any other file.
+pub fn find_sidecar(
+  path: &Path,
+  accept_encoding: &str,
+  is_servable: impl Fn(&Path) -> bool,
+) -> Option<(PathBuf, Encoding)> {
+  if !path.is_file() {
+    return None;
+  }
+
+  let available: Vec<Encoding> = Encoding::ALL
+    .into_iter()
+    .filter(|encoding| {
+      let sidecar = sidecar_path(path, *encoding);
+      sidecar.is_file() && is_servable(&sidecar)
+    })
+    .collect();
+  let encoding = negotiate(accept_encoding, &available)?;
+
+  Some((sidecar_path(path, encoding), encoding))
+}
+
+fn sidecar_path(path: &Path, encoding: Encoding) -> PathBuf {
+  let mut sidecar = path.as_os_str().to_owned();
+  sidecar.push(".");
+  sidecar.push(encoding.extension());
+
+  PathBuf::from(sidecar)
+}
+
+#[cfg(feature = "compression")]
+pub fn compress(content: &[u8], encoding: Encoding) -> std::io::Result<Vec<u8>> {
+  match encoding {
+    Encoding::Brotli => {
+      let mut writer =
+        brotli::CompressorWriter::new(Vec::new(), 4096, BROTLI_QUALITY, BROTLI_WINDOW);
+      writer.write_all(content)?;
+      Ok(writer.into_inner())
+    }
+    Encoding::Zstd => zstd::encode_all(content, ZSTD_LEVEL),
+    Encoding::Gzip => {
+      let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+      encoder.write_all(content)?;
+      encoder.finish()
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn negotiates_by_quality() {
+    let all = Encoding::ALL;
+
+    assert_eq!(negotiate("gzip", &all), Some(Encoding::Gzip));
+    assert_eq!(
+      negotiate("gzip;q=0.5, br;q=0.8", &all),
+      Some(Encoding::Brotli)
+    );
+    assert_eq!(negotiate("GZIP, zstd;q=0.1", &all), Some(Encoding::Gzip));
+    // Ties go to the server's preference
+    assert_eq!(negotiate("gzip, br, zstd", &all), Some(Encoding::Brotli));
+  }
+
+  #[test]
+  fn negotiates_wildcards_and_refusals() {
+    let all = Encoding::ALL;
+
+    assert_eq!(negotiate("*", &all), Some(Encoding::Brotli));
+    assert_eq!(negotiate("br;q=0, *", &all), Some(Encoding::Zstd));
+    assert_eq!(negotiate("gzip;q=0", &all), None);
+    assert_eq!(negotiate("identity", &all), None);
+    assert_eq!(negotiate("", &all), None);
+    assert_eq!(negotiate("br", &[Encoding::Gzip]), None);
+  }
+}
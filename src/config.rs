@@ -0,0 +1,142 @@
+use std::path::Path;
+
+use toml::{Table, Value};
+
+pub static CONFIG_FILE_NAME: &str = "swerve.toml";
+
+// Keys that only make sense on the command line
+static CLI_ONLY_KEYS: [&str; 3] = ["help", "version", "config"];
+
+/// A single key from the config file, translated into the command line arguments it stands for.
+pub struct ConfigEntry {
+  pub key: String,
+  pub args: Vec<String>,
+}
+
+/// Read a config file and translate each key into command line arguments, so the
+/// config can be parsed exactly like (and then be overridden by) the real arguments.
+///
+/// Keys match the long option names, with either dashes or underscores (eg. `root_index = true`).
+pub fn read_config(path: &Path) -> Result<Vec<ConfigEntry>, String> {
+  let content = std::fs::read_to_string(path)
+    .map_err(|e| format!("Failed to read config file {:?}: {}", path, e))?;
+  let table = content
+    .parse::<Table>()
+    .map_err(|e| format!("Failed to parse config file {:?}: {}", path, e))?;
+  let mut entries = Vec::new();
+
+  for (key, value) in table {
+    let option = key.replace('_', "-");
+
+    if CLI_ONLY_KEYS.contains(&option.as_str()) {
+      return Err(format!(
+        "Invalid key `{}` in config file {:?}: it can only be used on the command line",
+        key, path
+      ));
+    }
+
+    let args = if option == "path" {
+      // The served path is relative to the config file, not to wherever swerve was run from
+      let dir = path.parent().unwrap_or(Path::new("."));
+      let value = value_to_string(&key, &value)?;
+      vec![dir.join(value).to_string_lossy().to_string()]
+    } else {
+      match &value {
+        Value::Boolean(true) => vec![format!("--{}", option)],
+        Value::Boolean(false) => vec![],
+        Value::Array(values) => values
+          .iter()
+          .map(|value| Ok(format!("--{}={}", option, value_to_string(&key, value)?)))
+          .collect::<Result<_, String>>()?,
+        value => vec![format!("--{}={}", option, value_to_string(&key, value)?)],
+      }
+    };
+
+    entries.push(ConfigEntry { key, args });
+  }
+
+  Ok(entries)
+}
+
+fn value_to_string(key: &str, value: &Value) -> Result<String, String> {
+  match value {
+    Value::String(s) => Ok(s.clone()),
+    Value::Integer(i) => Ok(i.to_string()),
+    Value::Float(f) => Ok(f.to_string()),
+    _ => Err(format!(
+      "Invalid value for `{}` in config file: expected a string or number, got {}",
+      key,
+      value.type_str()
+    )),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::path::PathBuf;
+
+  fn read(name: &str, content: &str) -> Result<Vec<(String, Vec<String>)>, String> {
+    let dir = std::env::temp_dir().join(format!("swerve-config-{}-{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join(CONFIG_FILE_NAME), content).unwrap();
+
+    let entries = read_config(&dir.join(CONFIG_FILE_NAME));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    entries.map(|entries| {
+      entries
+        .into_iter()
+        .map(|entry| (entry.key, entry.args))
+        .collect()
+    })
+  }
+
+  #[test]
+  fn translates_values_into_arguments() {
+    let entries = read(
+      "values",
+      r#"
+        port = 3000
+        root_index = true
+        serve-directories = false
+        exclude = ["*.md", "*.txt"]
+        bind = "0.0.0.0"
+      "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+      entries,
+      [
+        ("bind".to_string(), vec!["--bind=0.0.0.0".to_string()]),
+        (
+          "exclude".to_string(),
+          vec!["--exclude=*.md".to_string(), "--exclude=*.txt".to_string()]
+        ),
+        ("port".to_string(), vec!["--port=3000".to_string()]),
+        ("root_index".to_string(), vec!["--root-index".to_string()]),
+        ("serve-directories".to_string(), vec![]),
+      ]
+    );
+  }
+
+  #[test]
+  fn resolves_the_path_relative_to_the_config() {
+    let entries = read("path", "path = \"dist\"").unwrap();
+    let path = PathBuf::from(&entries[0].1[0]);
+
+    assert!(path.ends_with("dist"));
+    assert!(path
+      .parent()
+      .unwrap()
+      .ends_with(format!("swerve-config-{}-path", std::process::id())));
+  }
+
+  #[test]
+  fn rejects_invalid_configs() {
+    assert!(read("cli-only", "version = true").is_err());
+    assert!(read("table", "[spa]\nfile = \"index.html\"").is_err());
+    assert!(read("syntax", "port = ").is_err());
+  }
+}
@@ -1,64 +1,162 @@
-use std::sync::OnceLock;
+#[cfg(feature = "hotreload")]
+use std::path::{Component, Path};
 
-use crate::{error, log};
+use crate::log;
 
-static INCLUDE_GLOBS: OnceLock<Vec<glob::Pattern>> = OnceLock::new();
-static EXCLUDE_GLOBS: OnceLock<Vec<glob::Pattern>> = OnceLock::new();
+// Changes to these are never worth reloading for: dependencies, version control and editor swap/backup files
+#[cfg(feature = "hotreload")]
+static DEFAULT_WATCH_IGNORES: [&str; 10] = [
+  "node_modules",
+  ".git",
+  ".hg",
+  ".svn",
+  "*.swp",
+  "*.swo",
+  "*.swx",
+  "*~",
+  ".#*",
+  "4913",
+];
 
-pub fn set_includes(includes: Vec<String>) {
-  let mut patterns = Vec::new();
+#[derive(Debug, Default, Clone)]
+pub struct Globs {
+  includes: Vec<glob::Pattern>,
+  excludes: Vec<glob::Pattern>,
+}
+
+impl Globs {
+  pub fn new(includes: &[String], excludes: &[String]) -> Result<Self, String> {
+    let mut globs = Globs::default();
+
+    for include in includes {
+      log!("Including: {}", include);
+      let pattern = glob::Pattern::new(include)
+        .map_err(|_| format!("Invalid include glob pattern: {}", include))?;
+      globs.includes.push(pattern);
+    }
 
-  for include in includes {
-    log!("Including: {}", include);
-    let pattern = glob::Pattern::new(&include)
-      .unwrap_or_else(|_| panic!("Invalid include glob pattern: {}", include));
-    patterns.push(pattern);
+    for exclude in excludes {
+      log!("Excluding: {}", exclude);
+      let pattern = glob::Pattern::new(exclude)
+        .map_err(|_| format!("Invalid exclude glob pattern: {}", exclude))?;
+      globs.excludes.push(pattern);
+    }
+
+    Ok(globs)
   }
 
-  INCLUDE_GLOBS
-    .set(patterns)
-    .unwrap_or_else(|e| error!("Failed to set include globs: {:?}", e));
-}
+  pub fn path_is_valid(&self, path: &str) -> bool {
+    // First see if it's excluded...
+    if !self.excludes.is_empty() {
+      for exclude in &self.excludes {
+        if exclude.matches(path) {
+          return false;
+        }
+      }
+    }
 
-pub fn set_excludes(excludes: Vec<String>) {
-  let mut patterns = Vec::new();
+    // Then see if it's included...
+    if self.includes.is_empty() {
+      return true;
+    }
+
+    for include in &self.includes {
+      log!("Checking if {} matches {}", path, include.as_str());
+      if include.matches(path) {
+        return true;
+      }
+    }
 
-  for exclude in excludes {
-    log!("Excluding: {}", exclude);
-    let pattern = glob::Pattern::new(&exclude)
-      .unwrap_or_else(|_| panic!("Invalid exclude glob pattern: {}", exclude));
-    patterns.push(pattern);
+    false
   }
+}
 
-  EXCLUDE_GLOBS
-    .set(patterns)
-    .unwrap_or_else(|e| error!("Failed to set exclude globs: {:?}", e));
+/// Paths the file watcher ignores changes to.
+#[cfg(feature = "hotreload")]
+#[derive(Debug, Clone)]
+pub struct WatchIgnores {
+  patterns: Vec<glob::Pattern>,
 }
 
-pub fn path_is_valid(path: &str) -> bool {
-  let includes = INCLUDE_GLOBS.get_or_init(Vec::new);
-  let excludes = EXCLUDE_GLOBS.get_or_init(Vec::new);
+#[cfg(feature = "hotreload")]
+impl WatchIgnores {
+  /// The default ignore list, plus the given glob patterns.
+  pub fn new(ignores: &[String]) -> Result<Self, String> {
+    let mut patterns = DEFAULT_WATCH_IGNORES
+      .iter()
+      .map(|ignore| glob::Pattern::new(ignore).unwrap())
+      .collect::<Vec<_>>();
 
-  // First see if it's excluded...
-  if !excludes.is_empty() {
-    for exclude in excludes {
-      if exclude.matches(path) {
-        return false;
-      }
+    for ignore in ignores {
+      log!("Ignoring changes to: {}", ignore);
+      // Watched directories are matched without a leading ./ (see is_ignored)
+      let pattern = glob::Pattern::new(ignore.strip_prefix("./").unwrap_or(ignore))
+        .map_err(|_| format!("Invalid watch ignore glob pattern: {}", ignore))?;
+      patterns.push(pattern);
     }
+
+    Ok(WatchIgnores { patterns })
   }
 
-  // Then see if it's included...
-  if includes.is_empty() {
-    return true;
+  /// Whether changes to the path (relative to the watched directory `dir`) are ignored. Patterns
+  /// can match either the whole path or any single part of it, so `*.map` ignores source maps
+  /// anywhere. They can also match the path prefixed with the watched directory as it was given,
+  /// so `src/generated/**` only ignores `generated` in `--watch src`, and not in the served directory.
+  pub fn is_ignored(&self, dir: &Path, path: &Path) -> bool {
+    let parts = to_parts(path);
+    let path = parts.join("/");
+    let dir_path = to_parts(&dir.join(&path)).join("/");
+
+    self.patterns.iter().any(|pattern| {
+      pattern.matches(&path)
+        || pattern.matches(&dir_path)
+        || parts.iter().any(|part| pattern.matches(part))
+    })
   }
+}
 
-  for include in includes {
-    log!("Checking if {} matches {}", path, include.as_str());
-    if include.matches(path) {
-      return true;
+#[cfg(feature = "hotreload")]
+fn to_parts(path: &Path) -> Vec<String> {
+  path
+    .components()
+    .filter(|component| component != &Component::CurDir)
+    .map(|component| component.as_os_str().to_string_lossy().into_owned())
+    .collect()
+}
+
+#[cfg(all(test, feature = "hotreload"))]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn ignores_paths_in_any_watched_directory() {
+    let ignores = WatchIgnores::new(&["*.map".to_string(), "cache/**".to_string()]).unwrap();
+
+    for dir in ["dist", "../src"] {
+      let dir = Path::new(dir);
+
+      assert!(ignores.is_ignored(dir, Path::new("js/app.js.map")));
+      assert!(ignores.is_ignored(dir, Path::new("cache/a/b.json")));
+      assert!(ignores.is_ignored(dir, Path::new("node_modules/x/index.js")));
+      assert!(ignores.is_ignored(dir, Path::new("index.html.swp")));
+      assert!(!ignores.is_ignored(dir, Path::new("js/app.js")));
     }
   }
 
-  false
+  #[test]
+  fn ignores_paths_in_one_watched_directory() {
+    let ignores = WatchIgnores::new(&[
+      "../src/generated/**".to_string(),
+      "./templates/*.bak".to_string(),
+    ])
+    .unwrap();
+
+    assert!(ignores.is_ignored(Path::new("../src"), Path::new("generated/types.ts")));
+    assert!(!ignores.is_ignored(Path::new("dist"), Path::new("generated/types.ts")));
+    assert!(!ignores.is_ignored(Path::new("../src"), Path::new("main.ts")));
+
+    assert!(ignores.is_ignored(Path::new("./templates"), Path::new("page.bak")));
+    assert!(ignores.is_ignored(Path::new("templates"), Path::new("page.bak")));
+    assert!(!ignores.is_ignored(Path::new("."), Path::new("page.bak")));
+  }
 }
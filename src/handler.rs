@@ -0,0 +1,464 @@
+use base64::Engine;
+use mime_guess::from_path;
+use std::{
+  fs,
+  path::{Path, PathBuf},
+  str::FromStr,
+};
+use tiny_http::{Header, HeaderField, Method, Request, Response};
+
+#[cfg(feature = "hotreload")]
+use crate::hotreload;
+use crate::{
+  cache,
+  compress::{self, Encoding},
+  error, html, log,
+  range::{self, RangeRequest},
+  resolve,
+  server::ServerConfig,
+  success, url, warn,
+};
+
+pub fn handle_request(request: Request, config: &ServerConfig) {
+  let start = std::time::Instant::now();
+  let url_path = match url::request_path(request.url()) {
+    Some(url_path) => url_path,
+    None => {
+      warn!("Request URL is not valid UTF-8: {}", request.url());
+      request
+        .respond(Response::empty(400))
+        .expect("Failed to respond with 400");
+      return;
+    }
+  };
+  let mut path = match resolve::join_url_path(&config.root, &url_path) {
+    Some(path) => path,
+    None => {
+      warn!("Request tried to escape the served directory: {}", url_path);
+      request
+        .respond(Response::empty(403))
+        .expect("Failed to respond with 403");
+      return;
+    }
+  };
+
+  log!("Incoming request for {:?}", path);
+
+  // Basic auth
+  if let Some((username, password)) = &config.credentials {
+    let b64 = base64::engine::general_purpose::STANDARD;
+    let auth_field = HeaderField::from_str("Authorization").unwrap();
+
+    if !request
+      .headers()
+      .iter()
+      .any(|header| header.field == auth_field)
+    {
+      warn!("No Authorization header, rejecting request");
+      // Respond with request to authorize
+      let mut res = Response::empty(401);
+      res.add_header(Header::from_str("WWW-Authenticate: Basic realm=\"Protected\"").unwrap());
+      request.respond(res).expect("Failed to respond with 401");
+      return;
+    }
+
+    let auth = request
+      .headers()
+      .iter()
+      .find(|header| header.field == auth_field)
+      .unwrap()
+      .value
+      .as_str();
+    let auth = auth.strip_prefix("Basic ").unwrap_or(auth);
+    let auth = b64.decode(auth).unwrap_or_default();
+    let auth = String::from_utf8(auth).unwrap_or_default();
+    let (attempt_username, attempt_password) = split_basic_auth(&auth).unwrap_or_default();
+
+    if attempt_username != username || attempt_password != password {
+      warn!("Invalid Authorization header, rejecting request");
+      // Respond with request to authorize
+      let mut res = Response::empty(401);
+      res.add_header(Header::from_str("WWW-Authenticate: Basic realm=\"Protected\"").unwrap());
+      request.respond(res).expect("Failed to respond with 401");
+      return;
+    }
+  }
+
+  // Hot reload clients connect on a reserved path of the main server
+  #[cfg(feature = "hotreload")]
+  if let Some(hub) = &config.hot_reload {
+    match url_path.as_str() {
+      hotreload::WS_PATH => return hotreload::accept_websocket(request, hub),
+      hotreload::EVENTS_PATH => return hotreload::accept_event_stream(request, hub),
+      _ => {}
+    }
+  }
+
+  // If the path is a dir but the URL does NOT end with a slash, redirect to version with slash
+  if path.is_dir() && !url_path.ends_with('/') {
+    warn!("URL does not have trailing slash, redirecting...");
+
+    let mut res = Response::empty(301);
+    let location = match url::split_url(request.url()) {
+      (raw_path, Some(query)) => format!("{}/?{}", raw_path, query),
+      (raw_path, None) => format!("{}/", raw_path),
+    };
+    res.add_header(Header::from_str(format!("Location: {}", location).as_str()).unwrap());
+    request.respond(res).expect("Failed to respond with 301");
+    return;
+  }
+
+  // If the path is nothing (root) or a directory, look for index.html or index.htm
+  if config.root_index {
+    if let Ok(dir) = fs::read_dir(path.clone()) {
+      log!("Looking for index.html or index.htm in {:?}", path);
+
+      // Read the dir, look for index.html or index.htm
+      let idx_files = ["index.html", "index.htm"];
+      for entry in dir {
+        let entry = entry.unwrap();
+        let entry_path = entry.path();
+        if idx_files.contains(&entry_path.file_name().unwrap().to_str().unwrap()) {
+          path = entry_path;
+          break;
+        }
+      }
+    }
+  }
+
+  // See if the path is valid
+  if !config.globs.path_is_valid(path.to_str().unwrap()) {
+    log!("Path is invalid due to glob patterns");
+    request
+      .respond(Response::empty(404))
+      .expect("Failed to respond with 404");
+    return;
+  }
+
+  // In SPA mode, client-side routes that don't exist on disk get the fallback document instead
+  if let Some(spa) = &config.spa {
+    if !path.exists() && is_spa_route(&request, &url_path) {
+      log!("No file for {:?}, serving SPA fallback {:?}", path, spa);
+      path = config.root.join(spa);
+    }
+  }
+
+  // Make sure we aren't led outside of the served directory by symlinks
+  if !resolve::is_allowed(&config.root, &path, config.follow_symlinks) {
+    warn!("Path is forbidden by the symlink policy: {:?}", path);
+    request
+      .respond(Response::empty(403))
+      .expect("Failed to respond with 403");
+    return;
+  }
+
+  // If the path is a directory, serve the directory
+  let response = if path.is_dir() && config.serve_directories {
+    #[allow(unused_mut)]
+    let mut html =
+      html::get_directory_html(&config.root, &url_path, config.follow_symlinks).into_bytes();
+
+    #[cfg(feature = "hotreload")]
+    if config.hot_reload.is_some() {
+      hotreload::inject_script(
+        &mut html,
+        config.reload_indicator,
+        path
+          .strip_prefix(&config.root)
+          .ok()
+          .map(hotreload::Page::Directory),
+      );
+    }
+
+    let etag = cache::content_etag(&html);
+    let etag_header = Header::from_str(format!("ETag: {}", etag).as_str()).unwrap();
+
+    if is_conditional_method(&request)
+      && cache::is_not_modified(get_header(&request, "If-None-Match"), None, &etag, None)
+    {
+      log!("Directory listing not modified, responding with 304");
+      request.respond(Response::empty(304).with_header(etag_header))
+    } else {
+      let mut res = Response::from_data(html);
+
+      res.add_header(Header::from_str("Content-Type: text/html").unwrap());
+      res.add_header(etag_header);
+
+      request.respond(res)
+    }
+  } else {
+    // Prefer a precompressed sidecar (eg. app.js.br) if the client accepts its encoding
+    #[allow(unused_mut)]
+    let mut sidecar = match get_header(&request, "Accept-Encoding") {
+      Some(accept_encoding) if get_header(&request, "Range").is_none() => {
+        compress::find_sidecar(&path, accept_encoding, |sidecar| {
+          resolve::is_allowed(&config.root, sidecar, config.follow_symlinks)
+            && config.globs.path_is_valid(&sidecar.to_string_lossy())
+        })
+      }
+      _ => None,
+    };
+
+    // Go by the Content-Type the file is served with, so anything served as HTML gets the script
+    #[cfg(feature = "hotreload")]
+    let inject_hotreload = config.hot_reload.is_some()
+      && from_path(&path).first_or_text_plain().essence_str() == mime_guess::mime::TEXT_HTML;
+
+    // Sidecars can't have the hot reload script injected into them
+    #[cfg(feature = "hotreload")]
+    if inject_hotreload {
+      sidecar = None;
+    }
+
+    let file_path = sidecar.as_ref().map_or(&path, |(sidecar, _)| sidecar);
+
+    match std::fs::read(file_path) {
+      #[allow(unused_mut)]
+      Ok(mut content) => {
+        #[cfg(not(feature = "hotreload"))]
+        let inject_hotreload = false;
+
+        #[cfg(feature = "hotreload")]
+        if inject_hotreload {
+          hotreload::inject_script(
+            &mut content,
+            config.reload_indicator,
+            path
+              .strip_prefix(&config.root)
+              .ok()
+              .map(hotreload::Page::File),
+          );
+        }
+
+        respond_with_file(request, config, &path, content, sidecar, inject_hotreload)
+      }
+      Err(_) => {
+        warn!("Not found: {:?}", path);
+        request.respond(Response::empty(404))
+      }
+    }
+  };
+
+  // Suppress/handle error
+  match response {
+    Ok(_) => {
+      success!("Reponse served for {:?}", path);
+    }
+    Err(e) => error!("Failed to serve {:?}: {:?}", path, e),
+  }
+
+  log!("Request took {:?}", start.elapsed());
+}
+
+/// Split `USERNAME:PASSWORD` credentials, as sent in basic auth headers or given to `--basic-auth`.
+pub fn split_basic_auth(auth: &str) -> Option<(&str, &str)> {
+  let mut parts = auth.split(':');
+  let username = parts.next()?;
+  let password = parts.next()?;
+
+  Some((username, password))
+}
+
+/// Respond with the content of a file. If a sidecar is given, the content is that of the
+/// precompressed sidecar file rather than the file at `path` itself. `injected` means something
+/// (ie. the hot reload script) was added to the content, so it no longer matches the file on disk.
+fn respond_with_file(
+  request: Request,
+  #[cfg_attr(not(feature = "compression"), allow(unused_variables))] config: &ServerConfig,
+  path: &Path,
+  #[allow(unused_mut)] mut content: Vec<u8>,
+  sidecar: Option<(PathBuf, Encoding)>,
+  injected: bool,
+) -> std::io::Result<()> {
+  let mime = from_path(path).first_or_text_plain();
+  let meta = fs::metadata(sidecar.as_ref().map_or(path, |(sidecar, _)| sidecar)).ok();
+
+  // Injected content can only be validated by what is actually sent, not by the file's mtime, or
+  // a copy cached with (or without) the script would still count as fresh
+  let (modified, mut etag) = if injected {
+    (None, cache::content_etag(&content))
+  } else {
+    (
+      meta.as_ref().and_then(|meta| meta.modified().ok()),
+      meta.as_ref().map(cache::file_etag).unwrap_or_default(),
+    )
+  };
+  #[allow(unused_mut)]
+  let mut encoding = sidecar.map(|(_, encoding)| encoding);
+
+  // Ranges are served from the uncompressed content, so don't compress if one was asked for
+  #[cfg(feature = "compression")]
+  let compress_with = if encoding.is_none()
+    && config.compress
+    && compress::is_compressible(&mime)
+    && content.len() >= compress::MIN_COMPRESS_SIZE
+    && get_header(&request, "Range").is_none()
+  {
+    get_header(&request, "Accept-Encoding")
+      .and_then(|accept_encoding| compress::negotiate(accept_encoding, &Encoding::ALL))
+  } else {
+    None
+  };
+
+  #[cfg(not(feature = "compression"))]
+  let compress_with: Option<Encoding> = None;
+
+  let last_modified = modified.map(cache::http_date);
+  let last_modified_header = last_modified.as_ref().map(|last_modified| {
+    Header::from_str(format!("Last-Modified: {}", last_modified).as_str()).unwrap()
+  });
+
+  // Compare against the tag of what would be sent, without compressing anything just to answer 304
+  let expected_etag = encoding
+    .or(compress_with)
+    .map_or(etag.clone(), |encoding| encoding.etag(&etag));
+
+  if is_conditional_method(&request)
+    && cache::is_not_modified(
+      get_header(&request, "If-None-Match"),
+      get_header(&request, "If-Modified-Since"),
+      &expected_etag,
+      modified,
+    )
+  {
+    log!("Not modified, responding with 304: {:?}", path);
+    let mut res = Response::empty(304)
+      .with_header(Header::from_str(format!("ETag: {}", expected_etag).as_str()).unwrap());
+
+    if let Some(last_modified_header) = last_modified_header {
+      res.add_header(last_modified_header);
+    }
+
+    // Same as the 200 would have, so caches key the validated response the same way
+    res.add_header(Header::from_str("Vary: Accept-Encoding").unwrap());
+
+    return request.respond(res);
+  }
+
+  #[cfg(feature = "compression")]
+  if let Some(compress_with) = compress_with {
+    match compress::compress(&content, compress_with) {
+      Ok(compressed) => {
+        log!(
+          "Compressed {:?} with {} ({} -> {} bytes)",
+          path,
+          compress_with.name(),
+          content.len(),
+          compressed.len()
+        );
+        content = compressed;
+        encoding = Some(compress_with);
+      }
+      Err(e) => error!("Failed to compress {:?}: {:?}", path, e),
+    }
+  }
+
+  // Only tag the response as encoded once it actually is
+  if let Some(encoding) = encoding {
+    etag = encoding.etag(&etag);
+  }
+
+  let len = content.len();
+
+  // Only honor Range on GET requests, and only if If-Range (if sent) still matches
+  let range = match get_header(&request, "Range") {
+    Some(range)
+      if request.method() == &Method::Get
+        && get_header(&request, "If-Range").is_none_or(|if_range| {
+          range::if_range_matches(
+            if_range,
+            &etag,
+            last_modified.as_deref().unwrap_or_default(),
+          )
+        }) =>
+    {
+      range::parse_range(range, len)
+    }
+    _ => RangeRequest::Full,
+  };
+
+  let (status, body, content_type, content_range) = match range {
+    RangeRequest::Full => (200, content, mime.to_string(), None),
+    RangeRequest::Partial(ranges) if ranges.len() == 1 => {
+      let range = &ranges[0];
+      log!("Serving range {:?} of {:?}", range, path);
+
+      (
+        206,
+        content[range.clone()].to_vec(),
+        mime.to_string(),
+        Some(range::content_range(range, len)),
+      )
+    }
+    RangeRequest::Partial(ranges) => {
+      log!("Serving {} ranges of {:?}", ranges.len(), path);
+      let (boundary, body) = range::multipart_body(&content, &ranges, mime.as_ref());
+
+      (
+        206,
+        body,
+        format!("multipart/byteranges; boundary={}", boundary),
+        None,
+      )
+    }
+    RangeRequest::Unsatisfiable => {
+      warn!("Requested range not satisfiable for {:?}", path);
+      (
+        416,
+        Vec::new(),
+        mime.to_string(),
+        Some(format!("bytes */{}", len)),
+      )
+    }
+  };
+
+  let body_len = body.len();
+  let mut res = Response::from_data(body).with_status_code(status);
+
+  // Headers
+  let content_type = Header::from_str(format!("Content-Type: {}", content_type).as_str()).unwrap();
+  let content_length = Header::from_str(format!("Content-Length: {}", body_len).as_str()).unwrap();
+
+  res.add_header(content_type);
+  res.add_header(content_length);
+  res.add_header(Header::from_str("Accept-Ranges: bytes").unwrap());
+  res.add_header(Header::from_str(format!("ETag: {}", etag).as_str()).unwrap());
+
+  if let Some(last_modified_header) = last_modified_header {
+    res.add_header(last_modified_header);
+  }
+
+  // The encoding (and so the response) can change based on Accept-Encoding
+  res.add_header(Header::from_str("Vary: Accept-Encoding").unwrap());
+
+  if let Some(encoding) = encoding {
+    res.add_header(
+      Header::from_str(format!("Content-Encoding: {}", encoding.name()).as_str()).unwrap(),
+    );
+  }
+
+  if let Some(content_range) = content_range {
+    res.add_header(Header::from_str(format!("Content-Range: {}", content_range).as_str()).unwrap());
+  }
+
+  request.respond(res)
+}
+
+fn get_header<'a>(request: &'a Request, field: &'static str) -> Option<&'a str> {
+  request
+    .headers()
+    .iter()
+    .find(|header| header.field.equiv(field))
+    .map(|header| header.value.as_str())
+}
+
+// Only navigations to extensionless paths are SPA routes, missing assets (eg. /app.js) should still 404
+fn is_spa_route(request: &Request, url_path: &str) -> bool {
+  matches!(request.method(), Method::Get | Method::Head)
+    && Path::new(url_path).extension().is_none()
+}
+
+// Conditional requests only make sense for safe methods
+fn is_conditional_method(request: &Request) -> bool {
+  matches!(request.method(), Method::Get | Method::Head)
+}
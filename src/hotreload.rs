@@ -1,115 +1,1133 @@
-use notify::{recommended_watcher, Event, EventKind, RecursiveMode, Watcher};
+//! Hot reloading: watching the served directory and telling open pages about changes.
+//!
+//! Pages get a small script injected that connects to a websocket at [`WS_PATH`], on the same
+//! host and port the page was served from. Every message sent on it is a JSON encoded
+//! [`ChangeEvent`], so other tools can connect to it and make use of the changes as well:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "kind": "css",
+//!   "paths": ["styles/main.css"],
+//!   "timestamp": 1760000000000,
+//!   "build": null
+//! }
+//! ```
+//!
+//! * `version` is [`PROTOCOL_VERSION`], which is bumped on incompatible changes to the format.
+//!   Fields may be added without bumping it, so unknown fields should be ignored.
+//! * `kind` is what the page should do about the change, see [`ChangeKind`].
+//! * `paths` are the changed files, relative to the served directory and separated by `/`. Changes
+//!   in other watched directories aren't listed. Pages only reload (or swap stylesheets) for changes
+//!   to themselves or something they loaded, unless `paths` is empty, in which case anything could
+//!   have changed.
+//! * `timestamp` is when the change was picked up, in milliseconds since the Unix epoch.
+//! * `build` is the outcome of the build run for the change (`success`, `exitCode` and `stderr`,
+//!   see [`BuildStatus`]), or `null` if there wasn't one.
+//!
+//! The same messages are also streamed as server-sent events from [`EVENTS_PATH`], one per `data:`
+//! line, for clients that can't use websockets. The injected script falls back to it by itself.
+//! Websockets are only served where swerve can get hold of the connection's socket (currently on
+//! Unix), elsewhere the upgrade is refused with a `501`.
+
+use flume::RecvTimeoutError;
+use notify::{recommended_watcher, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde_json::json;
 use std::{
-  net::{TcpListener, TcpStream},
+  collections::{HashMap, HashSet},
+  io::{ErrorKind, Read, Write},
+  net::{Shutdown, SocketAddr, TcpStream},
   path::{Path, PathBuf},
-  sync::mpsc,
+  str::FromStr,
+  sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, Mutex,
+  },
+  time::{Duration, Instant, SystemTime},
+};
+use tiny_http::{Header, Request, Response};
+use tungstenite::{handshake::derive_accept_key, protocol::Role, Message, WebSocket};
+
+use crate::{
+  build, error,
+  globs::{Globs, WatchIgnores},
+  log, warn,
 };
-use tungstenite::{accept_hdr, Message};
 
-use crate::{error, log};
+/// The path hot reload clients connect to on the main server.
+pub const WS_PATH: &str = "/__swerve/ws";
+
+/// Server-sent events endpoint streaming the same messages as [`WS_PATH`], for when websockets
+/// are blocked (eg. by a proxy).
+pub const EVENTS_PATH: &str = "/__swerve/events";
+
+/// Version of the [`ChangeEvent`] message format.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+// How often clients are pinged to see if they're still there
+const PING_INTERVAL: Duration = Duration::from_secs(5);
+// How long a pinged client gets to answer before it's taken to be gone
+const PONG_TIMEOUT: Duration = Duration::from_secs(5);
+// How long a websocket is read from at a time, before checking if there's something to send
+const READ_TIMEOUT: Duration = Duration::from_millis(50);
+// Events queued for a client that isn't reading them. Missing some is fine, one reload covers them all
+const CLIENT_QUEUE_SIZE: usize = 16;
 
 static HOTRELOAD_JS: &str = r#"
-const ws = new WebSocket("ws://127.0.0.1:__PORT__")
-ws.onmessage = function(event) {
-  if (event.data === "reload") {
+// Wrapped up so nothing leaks into the page's own globals
+(() => {
+const wsUrl = `${location.protocol === "https:" ? "wss:" : "ws:"}//${location.host}__WS_PATH__`
+const eventsUrl = "__EVENTS_PATH__"
+const showStatus = __SHOW_STATUS__
+// The file this page was served from, relative to the served directory. null if it wasn't served from a file.
+// For directory listings it's the directory, ending in a / (or empty for the root)
+const page = __PAGE__
+const isListing = __IS_LISTING__
+let connection
+// Websockets are tried first, server-sent events are the fallback for when they're blocked
+let useEvents = false
+let connected = false
+let retries = 0
+let lostConnection = false
+let unloading = false
+let retryTimeout
+
+function connect() {
+  if (useEvents) {
+    connection = new EventSource(eventsUrl)
+    // EventSource reconnects by itself, but without backing off or reloading afterwards
+    connection.onerror = () => {
+      connection.close()
+      onClose()
+    }
+  } else {
+    connection = new WebSocket(wsUrl)
+    connection.onclose = onClose
+  }
+
+  connection.onopen = onOpen
+  connection.onmessage = (event) => handleChange(JSON.parse(event.data))
+}
+
+function onOpen() {
+  // Whatever changed while swerve was gone (eg. being restarted) was missed, so start fresh
+  if (lostConnection) {
     location.reload()
+    return
   }
-};
 
-ws.onopen = (event) => {
-  console.log("Hot-reload connected")
+  console.log(`Hot-reload connected (${useEvents ? "server-sent events" : "websocket"})`)
+  connected = true
+  retries = 0
+  setStatus("connected", "Hot reload connected")
+}
+
+function onClose() {
+  if (unloading) {
+    return
+  }
+
+  if (connected) {
+    if (!lostConnection) {
+      console.log("Hot-reload disconnected, reconnecting...")
+    }
+
+    lostConnection = true
+  } else {
+    // Nothing got through yet, which could be a proxy blocking one of the transports or swerve
+    // not running at all. Alternate between them until one connects, then stick with it
+    useEvents = !useEvents
+  }
+
+  setStatus("disconnected", "Hot reload disconnected, reconnecting...")
+
+  // Back off exponentially, up to 10 seconds between attempts
+  retryTimeout = setTimeout(connect, Math.min(250 * 2 ** retries++, 10000))
+}
+
+function handleChange(change) {
+  if (change.build?.success) {
+    hideBuildError()
+  }
+
+  // Listings have no stylesheets to swap, but may have to list new ones
+  if (change.kind === "css" && !isListing) {
+    if (affectsPage(change.paths)) {
+      swapStylesheets(change.paths)
+    }
+  } else if (change.kind === "reload" || change.kind === "css") {
+    if (affectsPage(change.paths)) {
+      location.reload()
+    }
+  } else if (change.kind === "build-error") {
+    console.error(`Build failed (exit code ${change.build.exitCode}):\n${change.build.stderr}`)
+    showBuildError(change.build)
+  }
+}
+
+// Whether any of the changed files is this page or something it loaded, so other pages that are open
+// (eg. elsewhere on a docs site) are left alone
+function affectsPage(paths) {
+  if (page === null || paths.length === 0) {
+    return true
+  }
+
+  // Listings only show what's directly inside of the directory
+  if (isListing) {
+    return paths.some(path => path.slice(0, path.lastIndexOf("/") + 1) === page)
+  }
+
+  const loaded = new Set([page])
+
+  for (const entry of performance.getEntriesByType("resource")) {
+    const url = new URL(entry.name)
+
+    if (url.origin === location.origin) {
+      const path = urlPath(url)
+
+      // Directories could've been served their index file
+      if (path === "" || path.endsWith("/")) {
+        loaded.add(`${path}index.html`).add(`${path}index.htm`)
+      } else {
+        loaded.add(path)
+      }
+    }
+  }
+
+  return paths.some(path => loaded.has(path))
+}
+
+// The path a URL on this server points to, relative to the served directory like the changed paths.
+// A malformed escape in it would make decoding throw, so it's left as it is then
+function urlPath(url) {
+  try {
+    return decodeURIComponent(url.pathname).slice(1)
+  } catch {
+    return url.pathname.slice(1)
+  }
+}
+
+// A small dot in the corner showing whether hot reloading is connected
+function setStatus(state, title) {
+  if (!showStatus) {
+    return
+  }
+
+  let indicator = document.querySelector("swerve-status")
+
+  if (!indicator) {
+    indicator = document.createElement("swerve-status")
+    indicator.attachShadow({ mode: "open" }).innerHTML = `
+      <style>
+        div { position: fixed; right: 8px; bottom: 8px; z-index: 2147483646; width: 8px; height: 8px; border-radius: 50%; opacity: 0.6; transition: background 0.2s; }
+        .connected { background: rgb(34, 197, 94); }
+        .disconnected { background: rgb(245, 158, 11); animation: pulse 1s infinite alternate; }
+        @keyframes pulse { to { opacity: 0.2; } }
+      </style>
+      <div></div>
+    `
+    document.documentElement.appendChild(indicator)
+  }
+
+  const dot = indicator.shadowRoot.querySelector("div")
+  dot.className = state
+  dot.title = title
+}
+
+// Closing the connection because the page is going away isn't something to reconnect from
+window.addEventListener("pagehide", () => {
+  unloading = true
+  clearTimeout(retryTimeout)
+  connection.close()
+})
+
+// Unless it comes back from the back/forward cache, in which case it has to connect again
+window.addEventListener("pageshow", (event) => {
+  if (event.persisted) {
+    unloading = false
+    retries = 0
+    connect()
+  }
+})
+
+// Re-fetch the stylesheets that changed instead of reloading the page. If none of them are
+// linked directly (eg. they are @import-ed), re-fetch all of them
+function swapStylesheets(paths) {
+  const links = [...document.querySelectorAll('link[rel="stylesheet"]')]
+  const changed = links.filter(link => {
+    const url = new URL(link.href)
+    return url.origin === location.origin && paths.includes(urlPath(url))
+  })
+
+  for (const link of changed.length > 0 ? changed : links) {
+    const url = new URL(link.href)
+    url.searchParams.set("__swerve", Date.now())
+
+    // Only remove the old stylesheet once the new one is in, so the page doesn't flash unstyled
+    const swapped = link.cloneNode()
+    swapped.href = url.href
+    swapped.onload = swapped.onerror = () => link.remove()
+    link.after(swapped)
+  }
+}
+
+// Show the output of a failed build on top of the page, in a shadow root so the page's styles don't apply to it
+function showBuildError(build) {
+  hideBuildError()
+
+  const overlay = document.createElement("swerve-build-error")
+  const shadow = overlay.attachShadow({ mode: "open" })
+  shadow.innerHTML = `
+    <style>
+      .backdrop { position: fixed; inset: 0; z-index: 2147483647; display: flex; align-items: flex-start; justify-content: center; padding: 5vh 16px; background: rgba(0, 0, 0, 0.6); font-family: ui-monospace, Menlo, Consolas, monospace; }
+      .box { box-sizing: border-box; width: 100%; max-width: 960px; max-height: 90vh; display: flex; flex-direction: column; background: rgb(24, 24, 27); color: rgb(228, 228, 231); border-top: 4px solid rgb(239, 68, 68); border-radius: 4px; box-shadow: 0 8px 32px rgba(0, 0, 0, 0.5); }
+      .header { display: flex; align-items: center; justify-content: space-between; padding: 12px 16px; color: rgb(248, 113, 113); font-weight: bold; }
+      button { background: none; border: none; color: inherit; font: inherit; font-size: 20px; line-height: 1; cursor: pointer; }
+      pre { margin: 0; padding: 0 16px 16px; overflow: auto; white-space: pre-wrap; font-size: 13px; }
+    </style>
+    <div class="backdrop">
+      <div class="box">
+        <div class="header"><span class="title"></span><button title="Dismiss (Esc)">&times;</button></div>
+        <pre></pre>
+      </div>
+    </div>
+  `
+
+  // Build tools like to color their output, which would only show up as garbage here
+  const stderr = build.stderr.replace(/\x1b\[[0-9;]*[A-Za-z]/g, "")
+
+  shadow.querySelector(".title").textContent = build.exitCode === null ? "Build failed" : `Build failed with exit code ${build.exitCode}`
+  shadow.querySelector("pre").textContent = stderr || "The build command didn't print anything to stderr"
+  shadow.querySelector("button").onclick = hideBuildError
+  document.documentElement.appendChild(overlay)
 }
 
-// On unload send disconnect message
-window.beforeunload = () => {
-  ws.close()
+function hideBuildError() {
+  document.querySelector("swerve-build-error")?.remove()
 }
+
+document.addEventListener("keydown", (event) => {
+  if (event.key === "Escape") {
+    hideBuildError()
+  }
+})
+
+// The browser only keeps track of the first 250 resources by default
+performance.setResourceTimingBufferSize(10000)
+connect()
+})()
 "#;
 
-pub fn get_hotreload_js(port: u16) -> String {
-  HOTRELOAD_JS.replace("__PORT__", &port.to_string())
+fn get_hotreload_js(show_status: bool, page: Option<Page>) -> String {
+  let is_listing = matches!(page, Some(Page::Directory(_)));
+  let page = page.map(|page| match page {
+    Page::File(path) => to_url_path(path),
+    Page::Directory(path) if path.as_os_str().is_empty() => String::new(),
+    Page::Directory(path) => format!("{}/", to_url_path(path)),
+  });
+  // Escaped so a file name can't close the script tag
+  let page = json!(page).to_string().replace('<', "\\u003c");
+
+  HOTRELOAD_JS
+    .replace("__WS_PATH__", WS_PATH)
+    .replace("__EVENTS_PATH__", EVENTS_PATH)
+    .replace("__SHOW_STATUS__", &show_status.to_string())
+    .replace("__PAGE__", &page)
+    .replace("__IS_LISTING__", &is_listing.to_string())
 }
 
-pub fn watch(path: &Path, event_tx: flume::Sender<Vec<PathBuf>>) -> notify::Result<()> {
-  let (tx, rx) = mpsc::channel::<notify::Result<Event>>();
-  let mut watcher = recommended_watcher(tx)?;
+/// What an HTML document was served from, relative to the served directory.
+pub(crate) enum Page<'a> {
+  File(&'a Path),
+  /// A directory listing, which changes along with the files directly inside of the directory
+  Directory(&'a Path),
+}
 
-  watcher.watch(path, RecursiveMode::Recursive)?;
+/// Inject the hot reload script into an HTML document, before `</body>` or `</head>` if it has
+/// either, or at the very end if it has neither. `show_status` shows a connection indicator on the page.
+///
+/// `page` is what the document was served from, so the page can tell which changes concern it.
+/// Without one, the page reloads on every change.
+pub(crate) fn inject_script(html: &mut Vec<u8>, show_status: bool, page: Option<Page>) {
+  let script = format!("<script>{}</script>", get_hotreload_js(show_status, page));
+  let position = rfind_tag(html, b"</body>")
+    .or_else(|| rfind_tag(html, b"</head>"))
+    .unwrap_or(html.len());
 
-  loop {
-    match rx.recv() {
-      Ok(Ok(event)) => match event.kind {
-        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => {
-          log!("File changed ({:?}): {:?}", event.kind, event.paths);
-          if let Err(e) = event_tx.send(event.paths) {
-            error!("Watcher send error: {:?}", e);
-          }
-        }
-        _ => {}
+  html.splice(position..position, script.into_bytes());
+}
+
+// Paths in messages (and URLs) are separated by `/` on every platform
+fn to_url_path(path: &Path) -> String {
+  path
+    .components()
+    .map(|component| component.as_os_str().to_string_lossy())
+    .collect::<Vec<_>>()
+    .join("/")
+}
+
+// Tags are case-insensitive
+fn rfind_tag(html: &[u8], tag: &[u8]) -> Option<usize> {
+  html
+    .windows(tag.len())
+    .rposition(|window| window.eq_ignore_ascii_case(tag))
+}
+
+/// What a page should do about a change.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChangeKind {
+  /// Reload the page. Sent as `"reload"`
+  Reload,
+  /// Only stylesheets changed, which can be swapped in place without reloading. Sent as `"css"`
+  Css,
+  /// The build command failed, so there is nothing new to show. `build` has the details. Sent as `"build-error"`
+  BuildError,
+}
+
+impl ChangeKind {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      ChangeKind::Reload => "reload",
+      ChangeKind::Css => "css",
+      ChangeKind::BuildError => "build-error",
+    }
+  }
+}
+
+/// The outcome of a build command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildStatus {
+  pub success: bool,
+  /// `None` if the command was stopped by a signal
+  pub exit_code: Option<i32>,
+  pub stderr: String,
+}
+
+/// A message sent to hot reload clients, see the [module docs](self) for its JSON format.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeEvent {
+  pub kind: ChangeKind,
+  /// Changed files, relative to the served directory and separated by `/`
+  pub paths: Vec<String>,
+  pub timestamp: SystemTime,
+  pub build: Option<BuildStatus>,
+}
+
+impl ChangeEvent {
+  /// An event for files changed now, given relative to the served directory.
+  pub fn from_paths<P: AsRef<Path>>(paths: &[P]) -> Self {
+    let only_css = !paths.is_empty()
+      && paths.iter().all(|path| {
+        path
+          .as_ref()
+          .extension()
+          .is_some_and(|ext| ext.eq_ignore_ascii_case("css"))
+      });
+
+    ChangeEvent {
+      kind: if only_css {
+        ChangeKind::Css
+      } else {
+        ChangeKind::Reload
       },
-      Ok(Err(e)) => error!("Watcher send error: {:?}", e),
-      Err(e) => error!("Watcher receive error: {:?}", e),
+      paths: paths
+        .iter()
+        .map(|path| to_url_path(path.as_ref()))
+        .collect(),
+      timestamp: SystemTime::now(),
+      build: None,
+    }
+  }
+
+  pub fn to_json(&self) -> String {
+    let timestamp = self
+      .timestamp
+      .duration_since(SystemTime::UNIX_EPOCH)
+      .unwrap_or_default()
+      .as_millis() as u64;
+    let build = self.build.as_ref().map(|build| {
+      json!({
+        "success": build.success,
+        "exitCode": build.exit_code,
+        "stderr": build.stderr,
+      })
+    });
+
+    json!({
+      "version": PROTOCOL_VERSION,
+      "kind": self.kind.as_str(),
+      "paths": self.paths,
+      "timestamp": timestamp,
+      "build": build,
+    })
+    .to_string()
+  }
+}
+
+/// Fans change events out to every connected client, so every open tab gets every change.
+#[derive(Clone, Default)]
+pub(crate) struct Hub {
+  clients: Arc<Mutex<HashMap<usize, flume::Sender<ChangeEvent>>>>,
+  next_id: Arc<AtomicUsize>,
+  // Sent to clients connecting while the last build is broken, so reloading the page doesn't hide the error
+  build_error: Arc<Mutex<Option<ChangeEvent>>>,
+}
+
+impl Hub {
+  /// Register a new client. It is unregistered again once the returned `Client` is dropped.
+  pub fn register(&self) -> Client {
+    let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+    let (tx, rx) = flume::bounded(CLIENT_QUEUE_SIZE);
+
+    if let Some(event) = self.build_error.lock().unwrap().clone() {
+      tx.try_send(event).unwrap_or_default();
+    }
+
+    self.clients.lock().unwrap().insert(id, tx);
+
+    Client {
+      id,
+      hub: self.clone(),
+      rx,
     }
   }
+
+  /// Send the event to every registered client.
+  pub fn broadcast(&self, event: ChangeEvent) {
+    if let Some(build) = &event.build {
+      *self.build_error.lock().unwrap() = (!build.success).then(|| event.clone());
+    }
+
+    let mut clients = self.clients.lock().unwrap();
+
+    // Clients are normally unregistered on drop, but don't hold onto any that went away without it
+    clients.retain(|_, tx| {
+      !matches!(
+        tx.try_send(event.clone()),
+        Err(flume::TrySendError::Disconnected(_))
+      )
+    });
+  }
+
+  /// Unregister every client, which tells them to disconnect.
+  pub fn close(&self) {
+    self.clients.lock().unwrap().clear();
+  }
+
+  /// The amount of currently connected clients.
+  pub fn client_count(&self) -> usize {
+    self.clients.lock().unwrap().len()
+  }
+
+  fn unregister(&self, id: usize) {
+    self.clients.lock().unwrap().remove(&id);
+  }
+}
+
+/// A client registered with a `Hub`, receiving every broadcast change.
+pub(crate) struct Client {
+  id: usize,
+  hub: Hub,
+  rx: flume::Receiver<ChangeEvent>,
+}
+
+impl Client {
+  pub fn events(&self) -> &flume::Receiver<ChangeEvent> {
+    &self.rx
+  }
 }
 
-pub fn create_websocket(
-  port: u16,
-  event_rx: flume::Receiver<Vec<PathBuf>>,
-) -> Result<(), std::io::Error> {
-  let rx = event_rx.clone();
-  let server = TcpListener::bind(format!("127.0.0.1:{}", port))?;
-  log!("Hotreload listening on port {}", port);
+impl Drop for Client {
+  fn drop(&mut self) {
+    self.hub.unregister(self.id);
+  }
+}
+
+/// What to watch for and do about changes.
+pub(crate) struct WatchOptions {
+  /// Changes to served paths the globs don't allow serving are skipped
+  pub globs: Globs,
+  /// Matched relative to whichever watched directory a path is in
+  pub ignores: WatchIgnores,
+  /// Changes are only acted on once none have come in for this long, so a burst of them turns into a single event
+  pub debounce: Duration,
+  /// Build command to run before telling clients about changes
+  pub on_change: Option<String>,
+  /// Directories to watch on top of the served one, eg. sources that get built into it
+  pub extra_paths: Vec<PathBuf>,
+}
 
-  for stream in server.incoming() {
-    log!("Incoming hotreload connection");
+struct WatchedDir {
+  path: PathBuf,
+  // Some platforms report canonical paths, no matter how the watched path was given
+  canonical_path: Option<PathBuf>,
+  served: bool,
+}
 
-    let stream = stream?;
-    let rx = rx.clone();
+impl WatchedDir {
+  fn new(path: &Path, served: bool) -> Self {
+    WatchedDir {
+      path: path.to_path_buf(),
+      canonical_path: path.canonicalize().ok(),
+      served,
+    }
+  }
 
-    std::thread::spawn(move || ws_stream(rx, stream));
+  fn relative<'a>(&self, path: &'a Path) -> Option<&'a Path> {
+    path
+      .strip_prefix(&self.path)
+      .ok()
+      .or_else(|| path.strip_prefix(self.canonical_path.as_ref()?).ok())
   }
+}
 
-  Ok(())
+#[derive(Default)]
+struct Changes {
+  /// Relative to the served directory
+  served: Vec<PathBuf>,
+  /// In any of the other watched directories
+  other: Vec<PathBuf>,
 }
 
-fn ws_stream(
-  rx: flume::Receiver<Vec<PathBuf>>,
-  stream: TcpStream,
-) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-  // TODO this may be able to be used for more advanced/optimized hotreload somehow
-  let mut uri = None;
-  let mut headers = None;
-  let mut websocket = accept_hdr(
-    stream,
-    move |req: &tungstenite::http::Request<()>, res: tungstenite::http::Response<()>| {
-      uri.replace(req.uri().to_string());
-      headers.replace(res.headers().clone());
-      Ok(res)
+impl Changes {
+  fn extend(&mut self, changes: Changes) {
+    self.served.extend(changes.served);
+    self.other.extend(changes.other);
+  }
+}
+
+/// Watch the path (and any extra paths) for changes, broadcasting the changed paths (relative to
+/// `path`) through the hub. Watching stops once the returned watcher is dropped.
+pub(crate) fn watch(
+  path: &Path,
+  options: WatchOptions,
+  hub: Hub,
+) -> notify::Result<RecommendedWatcher> {
+  let WatchOptions {
+    globs,
+    ignores,
+    debounce,
+    on_change,
+    extra_paths,
+  } = options;
+  let dirs = std::iter::once(WatchedDir::new(path, true))
+    .chain(extra_paths.iter().map(|path| WatchedDir::new(path, false)))
+    .collect::<Vec<_>>();
+  let (change_tx, change_rx) = flume::unbounded::<Changes>();
+
+  let mut watcher = recommended_watcher(move |event: notify::Result<Event>| match event {
+    Ok(event) => match event.kind {
+      EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => {
+        let mut changes = Changes::default();
+
+        for path in &event.paths {
+          // Watched directories can be nested, in which case the path belongs to the innermost one
+          let watched = dirs
+            .iter()
+            .filter_map(|dir| Some((dir, dir.relative(path)?)))
+            .min_by_key(|(_, relative)| relative.components().count());
+
+          match watched {
+            Some((dir, relative)) if ignores.is_ignored(&dir.path, relative) => {}
+            // Globs are matched the same way as for requests, against the root joined with the path
+            Some((dir, relative))
+              if dir.served && globs.path_is_valid(&dir.path.join(relative).to_string_lossy()) =>
+            {
+              changes.served.push(relative.to_path_buf())
+            }
+            Some((dir, _)) if dir.served => {}
+            Some(_) => changes.other.push(path.clone()),
+            None => {}
+          }
+        }
+
+        if !changes.served.is_empty() || !changes.other.is_empty() {
+          log!(
+            "File changed ({:?}): {:?}",
+            event.kind,
+            changes
+              .served
+              .iter()
+              .chain(&changes.other)
+              .collect::<Vec<_>>()
+          );
+          change_tx.send(changes).unwrap_or_default();
+        }
+      }
+      _ => {}
     },
+    Err(e) => error!("Watcher error: {:?}", e),
+  })?;
+
+  watcher.watch(path, RecursiveMode::Recursive)?;
+
+  for extra_path in &extra_paths {
+    log!("Watching: {:?}", extra_path);
+    watcher.watch(extra_path, RecursiveMode::Recursive)?;
+  }
+
+  // Stops once the watcher (and with it the sender) is dropped
+  std::thread::spawn(move || {
+    let collect = |changes: &mut Changes| {
+      while let Ok(more) = change_rx.recv_timeout(debounce) {
+        changes.extend(more);
+      }
+    };
+
+    while let Ok(mut changes) = change_rx.recv() {
+      collect(&mut changes);
+
+      // Builds run one at a time. Whatever changes in the served directory while one runs is taken
+      // to be its output, which ends up in the same event rather than triggering another build (and
+      // so on, forever). Changes anywhere else mean the build is already out of date, so it runs again
+      let build = on_change.as_deref().map(|command| loop {
+        let status = build::run(command);
+        let mut during_build = Changes::default();
+
+        collect(&mut during_build);
+        changes.served.extend(during_build.served);
+
+        if during_build.other.is_empty() {
+          break status;
+        }
+
+        log!("Files changed during the build, running it again");
+      });
+
+      let mut seen = HashSet::new();
+      changes.served.retain(|path| seen.insert(path.clone()));
+
+      let mut event = ChangeEvent::from_paths(&changes.served);
+
+      match build {
+        Some(build) => {
+          if !build.success {
+            event.kind = ChangeKind::BuildError;
+          }
+
+          event.build = Some(build);
+        }
+        // Nothing outside of the served directory can be swapped in place. There's also no telling
+        // which pages it affects, so leaving the paths out makes all of them reload
+        None if !changes.other.is_empty() => {
+          event.kind = ChangeKind::Reload;
+          event.paths.clear();
+        }
+        None => {}
+      }
+
+      hub.broadcast(event);
+    }
+  });
+
+  Ok(watcher)
+}
+
+/// Upgrade a request for `WS_PATH` to a websocket, and push change events to it from a thread of its own.
+pub(crate) fn accept_websocket(request: Request, hub: &Hub) {
+  let is_websocket = request.headers().iter().any(|header| {
+    header.field.equiv("Upgrade") && header.value.as_str().eq_ignore_ascii_case("websocket")
+  });
+  let key = request
+    .headers()
+    .iter()
+    .find(|header| header.field.equiv("Sec-WebSocket-Key"))
+    .map(|header| derive_accept_key(header.value.as_str().as_bytes()));
+
+  let accept_key = match key {
+    Some(key) if is_websocket => key,
+    _ => {
+      warn!("Hotreload request is not a websocket upgrade, rejecting request");
+      request
+        .respond(Response::empty(400))
+        .unwrap_or_else(|e| error!("Failed to respond with 400: {:?}", e));
+      return;
+    }
+  };
+
+  // Without the socket the connection couldn't be closed while it's being read from. Pages fall
+  // back to the event stream when the upgrade is refused
+  let Some(socket) = request.remote_addr().and_then(|peer| find_socket(*peer)) else {
+    warn!("Can't serve hotreload websockets here, clients have to use the event stream");
+    request
+      .respond(Response::empty(501))
+      .unwrap_or_else(|e| error!("Failed to respond with 501: {:?}", e));
+    return;
+  };
+
+  let client = hub.register();
+  let hub = hub.clone();
+
+  log!(
+    "Incoming hotreload connection ({} connected)",
+    hub.client_count()
+  );
+
+  // The connection stays open for as long as the page does, so it can't take up a request thread
+  std::thread::spawn(move || {
+    let response = Response::empty(101).with_header(
+      Header::from_str(format!("Sec-WebSocket-Accept: {}", accept_key).as_str()).unwrap(),
+    );
+    let stream = request.upgrade("websocket", response);
+    let websocket = WebSocket::from_raw_socket(stream, Role::Server, None);
+
+    if let Err(e) = serve_client(&client, websocket, &socket) {
+      log!("Hotreload connection error: {}", e);
+    }
+
+    // However the client went away, close the connection so nothing is left reading from it
+    socket.shutdown(Shutdown::Both).unwrap_or_default();
+    drop(client);
+
+    log!(
+      "Hotreload client disconnected ({} connected)",
+      hub.client_count()
+    );
+  });
+}
+
+/// Stream change events to an `EventSource` client.
+pub(crate) fn accept_event_stream(request: Request, hub: &Hub) {
+  let client = hub.register();
+  let hub = hub.clone();
+
+  log!(
+    "Incoming hotreload event stream ({} connected)",
+    hub.client_count()
+  );
+
+  // Same as with websockets, the stream stays open for as long as the page does
+  std::thread::spawn(move || {
+    // A chunked response would hold events back until a whole chunk is filled, so the head is
+    // written by hand and the body simply lasts until the connection closes
+    let mut writer = request.into_writer();
+
+    if let Err(e) = serve_event_stream(&client, &mut writer) {
+      log!("Hotreload event stream error: {}", e);
+    }
+
+    drop(client);
+
+    log!(
+      "Hotreload client disconnected ({} connected)",
+      hub.client_count()
+    );
+  });
+}
+
+/// Write change events to an event stream until either side goes away. Clients can't send
+/// anything back, so a comment is written every now and then to find out if they're still there.
+fn serve_event_stream(client: &Client, writer: &mut impl Write) -> std::io::Result<()> {
+  writer.write_all(
+    b"HTTP/1.1 200 OK\r\n\
+      Content-Type: text/event-stream\r\n\
+      Cache-Control: no-cache\r\n\
+      Connection: close\r\n\r\n",
   )?;
+  writer.flush()?;
 
-  websocket
-    .get_mut()
-    .set_nonblocking(true)
-    .unwrap_or_default();
+  loop {
+    match client.events().recv_timeout(PING_INTERVAL) {
+      Ok(event) => write!(writer, "data: {}\n\n", event.to_json())?,
+      Err(RecvTimeoutError::Timeout) => writer.write_all(b": ping\n\n")?,
+      // The hub was closed, so the server is shutting down
+      Err(RecvTimeoutError::Disconnected) => return Ok(()),
+    }
+
+    writer.flush()?;
+  }
+}
+
+/// What the websocket thread has to tell the client thread.
+enum Incoming {
+  Pong,
+  /// The connection ended, because the client closed it or it broke
+  Closed(tungstenite::Result<()>),
+}
+
+enum Next {
+  Event(Result<ChangeEvent, flume::RecvError>),
+  Incoming(Result<Incoming, flume::RecvError>),
+}
+
+/// Push change events to a websocket client until either side goes away.
+///
+/// The websocket is read from for as long as the connection lasts, on a thread of its own. Its
+/// reads time out every now and then, so whatever this thread queued up can be sent in between.
+/// The pongs and close frames it reads are waited on together with the change events, and the
+/// client is pinged every so often, so dead connections get dropped.
+#[allow(clippy::result_large_err)]
+fn serve_client<S: Read + Write + Send + 'static>(
+  client: &Client,
+  websocket: WebSocket<S>,
+  socket: &TcpStream,
+) -> tungstenite::Result<()> {
+  socket.set_read_timeout(Some(READ_TIMEOUT))?;
+
+  let (outgoing_tx, outgoing_rx) = flume::unbounded();
+  let (incoming_tx, incoming_rx) = flume::unbounded();
+
+  std::thread::spawn(move || {
+    let result = run_websocket(websocket, &outgoing_rx, &incoming_tx);
+    incoming_tx
+      .send(Incoming::Closed(result))
+      .unwrap_or_default();
+  });
+
+  let mut ping_at = Instant::now() + PING_INTERVAL;
+  let mut pong_deadline = None;
 
   loop {
-    let msg = rx.try_recv();
+    let next = flume::Selector::new()
+      .recv(client.events(), Next::Event)
+      .recv(&incoming_rx, Next::Incoming)
+      .wait_deadline(pong_deadline.unwrap_or(ping_at));
+
+    match next {
+      Ok(Next::Event(Ok(event))) => outgoing_tx
+        .send(Message::text(event.to_json()))
+        .unwrap_or_default(),
+      // The hub was closed, so the server is shutting down. The websocket thread closes the
+      // connection once it sees there's nothing left to send
+      Ok(Next::Event(Err(_))) => {
+        drop(outgoing_tx);
+        let deadline = Instant::now() + PONG_TIMEOUT;
+
+        while let Ok(incoming) = incoming_rx.recv_deadline(deadline) {
+          if let Incoming::Closed(result) = incoming {
+            return result;
+          }
+        }
+        return Ok(());
+      }
+      Ok(Next::Incoming(Ok(Incoming::Pong))) => {
+        pong_deadline = None;
+        ping_at = Instant::now() + PING_INTERVAL;
+      }
+      Ok(Next::Incoming(Ok(Incoming::Closed(result)))) => return result,
+      // The websocket thread always says why it stopped, so this doesn't happen
+      Ok(Next::Incoming(Err(_))) => return Ok(()),
+      Err(_) if pong_deadline.is_some() => {
+        log!("Hotreload client didn't answer a ping, dropping it");
+        return Ok(());
+      }
+      Err(_) => {
+        outgoing_tx
+          .send(Message::Ping(Default::default()))
+          .unwrap_or_default();
+        pong_deadline = Some(Instant::now() + PONG_TIMEOUT);
+      }
+    }
+  }
+}
 
-    if msg.is_ok() {
-      websocket
-        .send(Message::Text("reload".to_string().into()))
-        .unwrap_or_else(|e| error!("Stream write error: {:?}", e));
+/// Read from the websocket until the connection ends, and send whatever was queued up in between
+/// reads. Once nothing more can be queued, the connection is closed.
+#[allow(clippy::result_large_err)]
+fn run_websocket<S: Read + Write>(
+  mut websocket: WebSocket<S>,
+  outgoing: &flume::Receiver<Message>,
+  incoming: &flume::Sender<Incoming>,
+) -> tungstenite::Result<()> {
+  loop {
+    match websocket.read() {
+      Ok(Message::Pong(_)) => incoming.send(Incoming::Pong).unwrap_or_default(),
+      // tungstenite queues the close reply, which is sent when flushing
+      Ok(Message::Close(_)) => {
+        return match websocket.flush() {
+          Err(tungstenite::Error::ConnectionClosed) => Ok(()),
+          result => result,
+        }
+      }
+      Ok(_) => {}
+      // The read timed out, which is the chance to send something
+      Err(tungstenite::Error::Io(e))
+        if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+      Err(e) => return Err(e),
     }
 
-    // Also try to read from the stream
-    if let Ok(msg) = websocket.read() {
-      if msg.is_empty() || msg.is_close() {
-        break;
+    loop {
+      match outgoing.try_recv() {
+        Ok(message) => websocket.send(message)?,
+        Err(flume::TryRecvError::Empty) => break,
+        Err(flume::TryRecvError::Disconnected) => {
+          websocket.close(None)?;
+          return websocket.flush();
+        }
       }
     }
   }
+}
+
+/// Get a handle on the socket of a connection by the address of its peer, so it can be shut down
+/// while another thread is blocked reading from it. tiny_http doesn't hand out its sockets, so it's
+/// looked for among the open file descriptors.
+#[cfg(unix)]
+fn find_socket(peer: SocketAddr) -> Option<TcpStream> {
+  use std::os::fd::{BorrowedFd, RawFd};
+
+  std::fs::read_dir("/dev/fd").ok()?.find_map(|entry| {
+    let fd: RawFd = entry.ok()?.file_name().to_str()?.parse().ok()?;
+    // SAFETY: the descriptor is only borrowed to duplicate it. If it was closed since it was
+    // listed, duplicating it fails, and if it was reused for something else, the peer won't match
+    let fd = unsafe { BorrowedFd::borrow_raw(fd) }
+      .try_clone_to_owned()
+      .ok()?;
+    let socket = TcpStream::from(fd);
+
+    (socket.peer_addr().ok()? == peer).then_some(socket)
+  })
+}
+
+#[cfg(not(unix))]
+fn find_socket(_peer: SocketAddr) -> Option<TcpStream> {
+  None
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
 
-  Ok(())
+  #[test]
+  fn every_client_gets_every_event() {
+    let hub = Hub::default();
+    let clients = [hub.register(), hub.register(), hub.register()];
+
+    hub.broadcast(ChangeEvent::from_paths(&["index.html"]));
+    hub.broadcast(ChangeEvent::from_paths(&["main.css"]));
+
+    for client in &clients {
+      let events: Vec<_> = client.events().drain().collect();
+      let paths: Vec<_> = events.iter().map(|event| event.paths.clone()).collect();
+
+      assert_eq!(paths, [["index.html"], ["main.css"]]);
+    }
+  }
+
+  #[test]
+  fn clients_unregister_on_drop() {
+    let hub = Hub::default();
+    let first = hub.register();
+    let second = hub.register();
+
+    assert_eq!(hub.client_count(), 2);
+
+    drop(first);
+    assert_eq!(hub.client_count(), 1);
+
+    hub.broadcast(ChangeEvent::from_paths(&["index.html"]));
+    assert_eq!(second.events().len(), 1);
+
+    drop(second);
+    assert_eq!(hub.client_count(), 0);
+  }
+
+  #[test]
+  fn closing_disconnects_clients() {
+    let hub = Hub::default();
+    let client = hub.register();
+
+    hub.close();
+
+    assert_eq!(hub.client_count(), 0);
+    assert!(client.events().recv().is_err());
+  }
+
+  #[test]
+  fn change_events_are_sent_as_json() {
+    let event = ChangeEvent {
+      kind: ChangeKind::Css,
+      paths: vec!["styles/main.css".to_string()],
+      timestamp: SystemTime::UNIX_EPOCH + Duration::from_millis(1_760_000_000_123),
+      build: None,
+    };
+
+    assert_eq!(
+      serde_json::from_str::<serde_json::Value>(&event.to_json()).unwrap(),
+      json!({
+        "version": 1,
+        "kind": "css",
+        "paths": ["styles/main.css"],
+        "timestamp": 1_760_000_000_123u64,
+        "build": null,
+      })
+    );
+  }
+
+  #[test]
+  fn build_errors_are_sent_as_json() {
+    let event = ChangeEvent {
+      kind: ChangeKind::BuildError,
+      paths: vec![],
+      timestamp: SystemTime::UNIX_EPOCH,
+      build: Some(BuildStatus {
+        success: false,
+        exit_code: Some(2),
+        stderr: "oops".to_string(),
+      }),
+    };
+
+    assert_eq!(
+      serde_json::from_str::<serde_json::Value>(&event.to_json()).unwrap(),
+      json!({
+        "version": 1,
+        "kind": "build-error",
+        "paths": [],
+        "timestamp": 0,
+        "build": { "success": false, "exitCode": 2, "stderr": "oops" },
+      })
+    );
+  }
+
+  fn inject(html: &str) -> String {
+    let mut html = html.as_bytes().to_vec();
+    inject_script(&mut html, false, None);
+
+    String::from_utf8(html).unwrap()
+  }
+
+  // Where the script ends up, with the script itself left out
+  fn injected_at(html: &str) -> String {
+    let injected = inject(html);
+    let start = injected.find("<script>").unwrap();
+    let end = injected.rfind("</script>").unwrap() + "</script>".len();
+
+    format!("{}|{}", &injected[..start], &injected[end..])
+  }
+
+  #[test]
+  fn injects_the_script_before_the_end_of_the_body() {
+    assert_eq!(
+      injected_at("<html><head></head><body><p>Hi</p></body></html>"),
+      "<html><head></head><body><p>Hi</p>|</body></html>"
+    );
+    // The last one, in case an earlier one is part of some text
+    assert_eq!(
+      injected_at("<body><pre></body></pre></body>"),
+      "<body><pre></body></pre>|</body>"
+    );
+  }
+
+  #[test]
+  fn injects_the_script_before_the_end_of_the_head_without_a_body() {
+    assert_eq!(
+      injected_at("<html><head><title>Hi</title></head></html>"),
+      "<html><head><title>Hi</title>|</head></html>"
+    );
+  }
+
+  #[test]
+  fn appends_the_script_without_a_head_or_body() {
+    assert_eq!(injected_at("<p>Hi</p>"), "<p>Hi</p>|");
+    assert_eq!(injected_at(""), "|");
+  }
+
+  #[test]
+  fn finds_tags_regardless_of_case() {
+    assert_eq!(
+      injected_at("<HEAD></HEAD><BODY>Hi</Body>"),
+      "<HEAD></HEAD><BODY>Hi|</Body>"
+    );
+    assert_eq!(injected_at("<head></HEAD>"), "<head>|</HEAD>");
+  }
 }
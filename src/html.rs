@@ -2,7 +2,11 @@ use std::path::Path;
 
 use chrono::{DateTime, Utc};
 
-use crate::error;
+use crate::{
+  error,
+  resolve::{self, SymlinkPolicy},
+  url::encode_path,
+};
 
 static HTML: &str = r#"
 <!DOCTYPE html>
@@ -58,7 +62,7 @@ static HTML: &str = r#"
 </html>
 "#;
 
-pub fn get_directory_html(root: &Path, path: &str) -> String {
+pub fn get_directory_html(root: &Path, path: &str, follow_symlinks: SymlinkPolicy) -> String {
   let path = if path.starts_with('/') {
     ".".to_owned() + path
   } else {
@@ -101,6 +105,11 @@ pub fn get_directory_html(root: &Path, path: &str) -> String {
         continue;
       }
     };
+    // Don't list symlinks that couldn't be followed anyway
+    if !resolve::is_allowed(root, &entry.path(), follow_symlinks) {
+      continue;
+    }
+
     let name = entry.file_name().into_string().unwrap();
     let mut path_as_str = path.replace('\\', "/").replace("./", "/");
 
@@ -113,14 +122,15 @@ pub fn get_directory_html(root: &Path, path: &str) -> String {
     let last_modified: DateTime<Utc> = last_modified.into();
     let size = meta.len();
 
+    let link = encode_path(&format!("{}/{}", path_as_str.trim_end_matches('/'), name));
+
     if entry.file_type().unwrap().is_dir() {
-      let href = format!("<a href=\"{}/{}\">{}/</a>", path_as_str, name, name);
+      let href = format!("<a href=\"{}\">{}/</a>", link, name);
       dirs.push_str(&format!("<li>{}</li>", href));
     } else {
       let href = format!(
-        "<a href=\"{}/{}\">{}</a> <span>{}</span> <span>{}</span>",
-        path_as_str,
-        name,
+        "<a href=\"{}\">{}</a> <span>{}</span> <span>{}</span>",
+        link,
         name,
         last_modified.format("%Y-%m-%d %H:%M:%S"),
         bytes_to_human(size)
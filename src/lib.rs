@@ -0,0 +1,28 @@
+//! Speedy, no-fuss local webserver for testing/serving static files or directories.
+//!
+//! The `swerve` binary is a thin wrapper around [`ServerBuilder`], which can also be used to embed
+//! swerve in other tools or integration tests.
+
+#[cfg(feature = "hotreload")]
+mod build;
+mod cache;
+mod compress;
+mod globs;
+mod handler;
+#[cfg(feature = "hotreload")]
+pub mod hotreload;
+mod html;
+pub mod log;
+mod range;
+mod resolve;
+mod server;
+#[cfg(feature = "tls")]
+pub mod tls;
+mod url;
+
+pub use handler::split_basic_auth;
+pub(crate) use log::{error, log, success, warn};
+pub use resolve::SymlinkPolicy;
+pub use server::{ServerBuilder, ServerHandle};
+#[cfg(feature = "tls")]
+pub use tiny_http::SslConfig;
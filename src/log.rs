@@ -36,30 +36,30 @@ pub fn log(s: impl AsRef<str> + Display, kind: Option<LogKind>) {
   );
 }
 
-#[macro_export]
-macro_rules! log {
+// Only for use within swerve, the binary has macros of its own. They're defined under other names,
+// as `log` would clash with the function and `warn` with the builtin attribute
+macro_rules! log_info {
   ($($arg:tt)*) => {
     $crate::log::log(format!($($arg)*), Some($crate::log::LogKind::Info))
   };
 }
 
-#[macro_export]
-macro_rules! success {
+macro_rules! log_success {
   ($($arg:tt)*) => {
     $crate::log::log(format!($($arg)*), Some($crate::log::LogKind::Success))
   };
 }
 
-#[macro_export]
-macro_rules! warn {
+macro_rules! log_warn {
   ($($arg:tt)*) => {
     $crate::log::log(format!($($arg)*), Some($crate::log::LogKind::Warn))
   };
 }
 
-#[macro_export]
-macro_rules! error {
+macro_rules! log_error {
   ($($arg:tt)*) => {
     $crate::log::log(format!($($arg)*), Some($crate::log::LogKind::Error))
   };
 }
+
+pub(crate) use {log_error as error, log_info as log, log_success as success, log_warn as warn};
@@ -1,30 +1,39 @@
-use base64::Engine;
 #[cfg(target_os = "windows")]
 use colored::control;
 use gumdrop::Options;
-#[cfg(feature = "hotreload")]
-use hotreload::get_hotreload_js;
-use mime_guess::from_path;
 #[cfg(not(windows))]
 use signal_hook::consts::{SIGINT, SIGTERM};
 use std::{
-  fs,
+  collections::HashSet,
   net::{IpAddr, Ipv4Addr},
-  path::PathBuf,
-  str::FromStr,
+  path::{Path, PathBuf},
+};
+use swerve::{
+  log::{set_silent, LogKind},
+  split_basic_auth, ServerBuilder, SymlinkPolicy,
 };
-use threadpool::ThreadPool;
-use tiny_http::{Header, HeaderField, Response, Server};
-
-use crate::log::set_silent;
 
-mod globs;
-#[cfg(feature = "hotreload")]
-mod hotreload;
-mod html;
-mod log;
+mod config;
 mod open;
 
+macro_rules! log {
+  ($($arg:tt)*) => {
+    swerve::log::log(format!($($arg)*), Some(LogKind::Info))
+  };
+}
+
+macro_rules! warn {
+  ($($arg:tt)*) => {
+    swerve::log::log(format!($($arg)*), Some(LogKind::Warn))
+  };
+}
+
+macro_rules! error {
+  ($($arg:tt)*) => {
+    swerve::log::log(format!($($arg)*), Some(LogKind::Error))
+  };
+}
+
 const VERSION: Option<&str> = option_env!("CARGO_PKG_VERSION");
 const HASH: Option<&str> = option_env!("GIT_HASH");
 
@@ -39,6 +48,13 @@ struct Args {
   #[options(free)]
   path: Option<std::path::PathBuf>,
 
+  #[options(
+    help = "Load options from a TOML config file (defaults to swerve.toml in the served directory)",
+    meta = "PATH",
+    no_short
+  )]
+  config: Option<PathBuf>,
+
   #[options(help = "Disable logging to stdout")]
   quiet: bool,
 
@@ -78,21 +94,106 @@ struct Args {
   threads: usize,
 
   #[cfg(feature = "hotreload")]
-  #[options(help = "Enable and set the hot-reloading port", meta = "PORT")]
-  hot_reload: Option<u16>,
+  #[options(help = "Reload open pages when files change")]
+  hot_reload: bool,
+
+  #[cfg(feature = "hotreload")]
+  #[options(
+    no_short,
+    help = "Wait for changes to stop for this long before reloading",
+    meta = "MS",
+    default = "100"
+  )]
+  reload_debounce: u64,
+
+  #[cfg(feature = "hotreload")]
+  #[options(
+    no_short,
+    help = "Don't reload for changes to paths matching this glob pattern, or only those in one watched directory with DIR/GLOB (node_modules, .git and editor swap files are always ignored)",
+    meta = "GLOB"
+  )]
+  watch_ignore: Vec<String>,
+
+  #[cfg(feature = "hotreload")]
+  #[options(
+    no_short,
+    help = "Shell command to run when files change, before reloading (implies --hot-reload)",
+    meta = "COMMAND"
+  )]
+  on_change: Option<String>,
+
+  #[cfg(feature = "hotreload")]
+  #[options(
+    no_short,
+    help = "Also watch this directory for changes (implies --hot-reload)",
+    meta = "PATH"
+  )]
+  watch: Vec<PathBuf>,
+
+  #[cfg(feature = "hotreload")]
+  #[options(
+    no_short,
+    help = "Don't show the hot reload connection status on pages"
+  )]
+  no_reload_indicator: bool,
 
   #[options(
     help = "Enable and set basic auth credentials",
     meta = "USERNAME:PASSWORD"
   )]
   basic_auth: Option<String>,
+
+  #[cfg(feature = "tls")]
+  #[options(
+    help = "Serve over HTTPS with a generated (and cached) self-signed certificate",
+    no_short
+  )]
+  https: bool,
+
+  #[cfg(feature = "tls")]
+  #[options(
+    help = "Serve over HTTPS using this PEM certificate",
+    meta = "PATH",
+    no_short
+  )]
+  tls_cert: Option<PathBuf>,
+
+  #[cfg(feature = "tls")]
+  #[options(help = "Private key (PEM) for --tls-cert", meta = "PATH", no_short)]
+  tls_key: Option<PathBuf>,
+
+  #[options(
+    help = "Whether to follow symlinks: never, within-root or always",
+    default = "within-root",
+    meta = "POLICY",
+    no_short
+  )]
+  follow_symlinks: SymlinkPolicy,
+
+  #[options(
+    help = "Serve FILE for routes that don't exist, for single-page apps (FILE defaults to index.html)",
+    meta = "FILE",
+    no_short
+  )]
+  spa: Option<String>,
+
+  #[cfg(feature = "compression")]
+  #[options(
+    help = "Compress responses when the client supports it (the default, unless the config file sets no_compress)",
+    no_short
+  )]
+  compress: bool,
+
+  #[cfg(feature = "compression")]
+  #[options(help = "Never compress responses on the fly", no_short)]
+  no_compress: bool,
 }
 
 pub fn main() {
   #[cfg(target_os = "windows")]
   control::set_virtual_terminal(true).unwrap_or_default();
 
-  let opts = Args::parse_args_default_or_exit();
+  let opts = parse_args();
 
   if opts.version {
     println!(
@@ -104,10 +205,6 @@ pub fn main() {
   }
 
   let port = opts.port;
-  let (username, password) =
-    split_basic_auth(&opts.basic_auth.unwrap_or_default()).unwrap_or_default();
-  let server = Server::http(format!("{}:{}", opts.bind, port)).unwrap();
-  // This is an Arc because it's used in the threadpool
   let local_path = opts.path.unwrap_or(std::path::PathBuf::from("."));
   let addr = if opts.bind == "0.0.0.0" {
     local_ip_address::local_ip()
@@ -119,31 +216,120 @@ pub fn main() {
 
   set_silent(opts.quiet);
 
+  if let Some(config) = &opts.config {
+    log!("Using config file {:?}", config);
+  }
+
   // If the path is the current dir, warn just in case
-  if local_path == PathBuf::from(".") {
+  if local_path == Path::new(".") {
     warn!("Serving current directory");
   }
 
-  // Set includes and excludes
-  globs::set_includes(opts.include);
-  globs::set_excludes(opts.exclude);
-
   if opts.threads == 1 {
     warn!(
       "Running in single-threaded mode! You may benefit from running with the --threads option"
     );
   }
 
+  let mut builder = ServerBuilder::new(&local_path)
+    .bind(&opts.bind)
+    .port(port)
+    .threads(opts.threads)
+    .root_index(opts.root_index)
+    .serve_directories(opts.serve_directories)
+    .follow_symlinks(opts.follow_symlinks);
+
+  for include in opts.include {
+    builder = builder.include(include);
+  }
+
+  for exclude in opts.exclude {
+    builder = builder.exclude(exclude);
+  }
+
+  // The config file is there for swerve, not to be served along with the site
+  if let Some(exclude) = opts
+    .config
+    .as_deref()
+    .and_then(|config| config_exclude(&local_path, config))
+  {
+    builder = builder.exclude(exclude);
+  }
+
+  if let Some((username, password)) = opts.basic_auth.as_deref().and_then(split_basic_auth) {
+    if !username.is_empty() && !password.is_empty() {
+      builder = builder.basic_auth(username, password);
+    }
+  }
+
+  if let Some(spa) = opts.spa {
+    builder = builder.spa(spa);
+  }
+
+  #[cfg(feature = "compression")]
+  {
+    builder = builder.compress(!opts.no_compress);
+  }
+
+  #[cfg(feature = "tls")]
+  {
+    let ssl = match (opts.https, &opts.tls_cert, &opts.tls_key) {
+      (_, Some(cert), Some(key)) => Some(swerve::tls::load_ssl_config(cert, key)),
+      (_, Some(_), None) | (_, None, Some(_)) => Some(Err(
+        "--tls-cert and --tls-key must be used together".to_string(),
+      )),
+      (true, None, None) => Some(swerve::tls::self_signed_ssl_config(&opts.bind)),
+      (false, None, None) => None,
+    }
+    .transpose()
+    .unwrap_or_else(|e| {
+      error!("{}", e);
+      std::process::exit(1);
+    });
+
+    if let Some(ssl) = ssl {
+      builder = builder.tls(ssl);
+    }
+  }
+
+  #[cfg(feature = "hotreload")]
+  {
+    builder = builder
+      .hot_reload(opts.hot_reload)
+      .reload_debounce(std::time::Duration::from_millis(opts.reload_debounce));
+
+    for ignore in opts.watch_ignore {
+      builder = builder.watch_ignore(ignore);
+    }
+
+    if let Some(command) = opts.on_change {
+      builder = builder.on_change(command);
+    }
+
+    for path in opts.watch {
+      builder = builder.watch(path);
+    }
+
+    builder = builder.reload_indicator(!opts.no_reload_indicator);
+  }
+
+  let server = builder.start().unwrap_or_else(|e| {
+    error!("Failed to start server: {}", e);
+    std::process::exit(1);
+  });
+  let scheme = if server.is_secure() { "https" } else { "http" };
+
   log!("Serving path: {:?}", local_path);
   log!(
-    "Access by visiting http://{}:{} in your browser",
+    "Access by visiting {}://{}:{} in your browser",
+    scheme,
     addr,
-    port
+    server.addr().port()
   );
 
   // Open in default browser
   if opts.open {
-    open::open_in_browser(&format!("http://{}:{}", addr, port));
+    open::open_in_browser(&format!("{}://{}:{}", scheme, addr, server.addr().port()));
   }
 
   // Create signal handler
@@ -163,169 +349,320 @@ pub fn main() {
     }
   });
 
-  #[cfg(feature = "hotreload")]
-  if let Some(port) = opts.hot_reload {
-    let (event_tx, event_rx) = flume::unbounded::<Vec<PathBuf>>();
-    let local_path = local_path.clone();
+  server.join();
+}
+
+fn parse_args() -> Args {
+  let mut args = std::env::args();
+  let program = args.next().unwrap_or("swerve".to_string());
+  let args: Vec<String> = args.collect();
+  let cli_options = given_options(&args);
+  let args = normalize_args(args);
+  let exit_with_error = |e: String| -> ! {
+    eprintln!("{}: {}", program, e);
+    std::process::exit(2);
+  };
 
-    std::thread::spawn(move || hotreload::watch(&local_path, event_tx));
-    std::thread::spawn(move || hotreload::create_websocket(port, event_rx));
+  let mut opts = Args::parse_args_default(&args).unwrap_or_else(|e| exit_with_error(e.to_string()));
+
+  if opts.help_requested() {
+    eprintln!("Usage: {} [OPTIONS]", program);
+    eprintln!();
+    eprintln!("{}", Args::usage());
+    std::process::exit(0);
   }
 
-  let pool = ThreadPool::new(opts.threads);
-
-  for request in server.incoming_requests() {
-    let local_path = local_path.clone();
-    let username = username.clone();
-    let password = password.clone();
-
-    pool.execute(move || {
-      let start = std::time::Instant::now();
-      // Remove leading slash
-      let path = request.url().strip_prefix('/').unwrap_or(request.url());
-      let mut path = local_path.join(PathBuf::from(path));
-
-      log!("Incoming request for {:?}", path);
-
-      // Basic auth
-      if !username.is_empty() && !password.is_empty() {
-        let b64 = base64::engine::general_purpose::STANDARD;
-        let auth_field = HeaderField::from_str("Authorization").unwrap();
-
-        if !request
-          .headers()
-          .iter()
-          .any(|header| header.field == auth_field)
-        {
-          warn!("No Authorization header, rejecting request");
-          // Respond with request to authorize
-          let mut res = Response::empty(401);
-          res.add_header(Header::from_str("WWW-Authenticate: Basic realm=\"Protected\"").unwrap());
-          request.respond(res).expect("Failed to respond with 401");
-          return;
-        }
+  // Use the given config file, or a swerve.toml in the served directory if there is one
+  let config_path = opts.config.clone().or_else(|| {
+    let path = opts
+      .path
+      .clone()
+      .unwrap_or(PathBuf::from("."))
+      .join(config::CONFIG_FILE_NAME);
+    path.is_file().then_some(path)
+  });
 
-        let auth = request
-          .headers()
-          .iter()
-          .find(|header| header.field == auth_field)
-          .unwrap()
-          .value
-          .as_str();
-        let auth = auth.strip_prefix("Basic ").unwrap_or(auth);
-        let auth = b64.decode(auth).unwrap_or_default();
-        let auth = String::from_utf8(auth).unwrap_or_default();
-        let (attempt_username, attempt_password) = split_basic_auth(&auth).unwrap_or_default();
-
-        if attempt_username != username || attempt_password != password {
-          warn!("Invalid Authorization header, rejecting request");
-          // Respond with request to authorize
-          let mut res = Response::empty(401);
-          res.add_header(Header::from_str("WWW-Authenticate: Basic realm=\"Protected\"").unwrap());
-          request.respond(res).expect("Failed to respond with 401");
-          return;
+  if let Some(config_path) = config_path {
+    let entries = config::read_config(&config_path).unwrap_or_else(|e| exit_with_error(e));
+    let mut config_args = Vec::new();
+
+    for entry in entries {
+      if entry.key == "path" {
+        // The path given on the command line wins
+        if opts.path.is_none() {
+          config_args.extend(entry.args);
         }
+        continue;
       }
 
-      // If the path is a dir but the URL does NOT end with a slash, redirect to version with slash
-      if path.is_dir() && !request.url().ends_with('/') {
-        warn!("URL does not have trailing slash, redirecting...");
+      let option = format!("--{}", entry.key.replace('_', "-"));
 
-        let mut res = Response::empty(301);
-        res.add_header(Header::from_str(format!("Location: {}/", request.url()).as_str()).unwrap());
-        request.respond(res).expect("Failed to respond with 301");
-        return;
+      if !Args::usage()
+        .split_whitespace()
+        .any(|token| token == option)
+      {
+        exit_with_error(format!(
+          "Unknown key `{}` in config file {:?}",
+          entry.key, config_path
+        ));
       }
 
-      // If the path is nothing (root) or a directory, look for index.html or index.htm
-      if opts.root_index {
-        if let Ok(dir) = fs::read_dir(path.clone()) {
-          log!("Looking for index.html or index.htm in {:?}", path);
-
-          // Read the dir, look for index.html or index.htm
-          let idx_files = ["index.html", "index.htm"];
-          for entry in dir {
-            let entry = entry.unwrap();
-            let entry_path = entry.path();
-            if idx_files.contains(&entry_path.file_name().unwrap().to_str().unwrap()) {
-              path = entry_path;
-              break;
-            }
-          }
-        }
+      // Options given on the command line replace the config's value, rather than adding to it
+      if cli_options.contains(&option) {
+        continue;
       }
 
-      // See if the path is valid
-      if !globs::path_is_valid(path.to_str().unwrap()) {
-        log!("Path is invalid due to glob patterns");
-        request
-          .respond(Response::empty(404))
-          .expect("Failed to respond with 404");
-        return;
-      }
+      let entry_args = normalize_args(entry.args);
 
-      // If the path is a directory, serve the directory
-      let response = if path.is_dir() && opts.serve_directories {
-        let html = html::get_directory_html(&local_path, request.url());
-        let mut res = Response::from_string(html);
+      if let Err(e) = Args::parse_args_default(&entry_args) {
+        exit_with_error(format!(
+          "Invalid value for `{}` in config file {:?}: {}",
+          entry.key, config_path, e
+        ));
+      }
 
-        res.add_header(Header::from_str("Content-Type: text/html").unwrap());
+      config_args.extend(entry_args);
+    }
 
-        request.respond(res)
-      } else {
-        match std::fs::read(&path) {
-          #[allow(unused_mut)]
-          Ok(mut content) => {
-            #[cfg(feature = "hotreload")]
-            if let Some(port) = opts.hot_reload {
-              let path_str = path.to_str().unwrap_or_default();
+    // Command line arguments come last, so they override anything from the config
+    config_args.extend(args);
+    opts =
+      Args::parse_args_default(&config_args).unwrap_or_else(|e| exit_with_error(e.to_string()));
+    opts.config = Some(config_path);
+  }
 
-              if path_str.ends_with(".html") || path_str.ends_with(".htm") {
-                let html = format!("<script>{}</script>", get_hotreload_js(port));
+  #[cfg(feature = "compression")]
+  if opts.compress && opts.no_compress {
+    exit_with_error("--compress and --no-compress can't be used together".to_string());
+  }
 
-                // Append to the end of content
-                content.append(&mut html.as_bytes().to_vec());
-              }
-            }
+  opts
+}
 
-            let mime = from_path(&path).first_or_text_plain();
-            let mut res = Response::from_data(content.clone());
+/// An option as listed in the usage text.
+struct OptionSpec {
+  short: Option<char>,
+  long: String,
+  takes_value: bool,
+}
 
-            // Headers
-            let content_type =
-              Header::from_str(format!("Content-Type: {}", mime).as_str()).unwrap();
-            let content_length =
-              Header::from_str(format!("Content-Length: {}", content.len()).as_str()).unwrap();
+// gumdrop doesn't tell which options were actually given, so go by the usage text instead
+fn option_specs() -> Vec<OptionSpec> {
+  Args::usage()
+    .lines()
+    .filter_map(|line| {
+      // eg. "-e, --exclude GLOB        List of glob patterns to exclude"
+      let spec = line.trim().split("  ").next()?;
+      let mut tokens = spec.split_whitespace().peekable();
+      let short = tokens
+        .next_if(|token| token.len() == 3 && token.starts_with('-') && token.ends_with(','))
+        .and_then(|token| token.chars().nth(1));
+      let long = tokens.next().filter(|token| token.starts_with("--"))?;
+
+      Some(OptionSpec {
+        short,
+        long: long.to_string(),
+        takes_value: tokens.next().is_some(),
+      })
+    })
+    .collect()
+}
 
-            res.add_header(content_type);
-            res.add_header(content_length);
+/// The long names of all options in the (not yet normalized) arguments, eg. `--exclude` for `-e*.md`.
+fn given_options(args: &[String]) -> HashSet<String> {
+  let specs = option_specs();
+  let mut given = HashSet::new();
+  let mut args = args.iter().peekable();
+
+  while let Some(arg) = args.next() {
+    if let Some(long) = arg.strip_prefix("--") {
+      let (long, value) = match long.split_once('=') {
+        Some((long, value)) => (long, Some(value)),
+        None => (long, None),
+      };
+      let long = format!("--{}", long);
 
-            request.respond(res)
-          }
-          Err(_) => {
-            warn!("Not found: {:?}", path);
-            request.respond(Response::empty(404))
-          }
+      if let Some(spec) = specs.iter().find(|spec| spec.long == long) {
+        // The value is the next argument, unless it's optional (eg. --spa) and left out
+        if spec.takes_value && value.is_none() {
+          args.next_if(|value| !value.starts_with('-'));
         }
-      };
+      }
+
+      given.insert(long);
+    } else if let Some(shorts) = arg.strip_prefix('-') {
+      // Short flags can be grouped (eg. -dH), up until one that takes a value
+      for (i, short) in shorts.char_indices() {
+        let Some(spec) = specs.iter().find(|spec| spec.short == Some(short)) else {
+          break;
+        };
+
+        given.insert(spec.long.clone());
 
-      // Suppress/handle error
-      match response {
-        Ok(_) => {
-          success!("Reponse served for {:?}", path);
+        if spec.takes_value {
+          if i + short.len_utf8() == shorts.len() {
+            args.next_if(|value| !value.starts_with('-'));
+          }
+          break;
         }
-        Err(e) => error!("Failed to serve {:?}: {:?}", path, e),
       }
+    }
+  }
 
-      log!("Request took {:?}", start.elapsed());
-    });
+  // Negated flags override their counterpart too, eg. --no-compress for compress = true
+  let counterparts: Vec<String> = given
+    .iter()
+    .map(|long| match long.strip_prefix("--no-") {
+      Some(flag) => format!("--{}", flag),
+      None => format!("--no-{}", &long[2..]),
+    })
+    .collect();
+  given.extend(counterparts);
+
+  given
+}
+
+// gumdrop doesn't support options with optional values, so --spa takes the next argument as its file
+// unless it's another option or a directory (most likely the one being served), and otherwise means --spa=index.html.
+// Hot reloading used to take a port of its own, which is skipped so older commands and configs keep working
+fn normalize_args(args: Vec<String>) -> Vec<String> {
+  let mut normalized = Vec::new();
+  let mut args = args.into_iter().peekable();
+
+  while let Some(arg) = args.next() {
+    if arg == "--spa" {
+      let file = args
+        .next_if(|file| !file.starts_with('-') && !Path::new(file).is_dir())
+        .unwrap_or("index.html".to_string());
+      normalized.push(format!("--spa={}", file));
+    } else if arg.starts_with("--hot-reload=") {
+      normalized.push("--hot-reload".to_string());
+    } else if let Some(flag) = flag_with_value(&arg, "true") {
+      normalized.push(flag.to_string());
+    } else if flag_with_value(&arg, "false").is_some() {
+      // Only there to override the config file
+    } else if arg == "--hot-reload" || arg == "-H" {
+      normalized.push(arg);
+      args.next_if(|port| port.parse::<u16>().is_ok());
+    } else {
+      normalized.push(arg);
+    }
   }
+
+  normalized
+}
+
+// Flags don't take values, but --flag=false is a way to turn off one that the config file turns on
+fn flag_with_value<'a>(arg: &'a str, value: &str) -> Option<&'a str> {
+  let (flag, arg_value) = arg.split_once('=')?;
+
+  (arg_value == value
+    && option_specs()
+      .iter()
+      .any(|spec| spec.long == flag && !spec.takes_value))
+  .then_some(flag)
 }
 
-fn split_basic_auth(auth: &str) -> Option<(String, String)> {
-  let mut parts = auth.split(':');
-  let username = parts.next()?;
-  let password = parts.next()?;
+/// A glob pattern matching exactly the config file, if it's somewhere inside the served directory.
+fn config_exclude(root: &Path, config: &Path) -> Option<String> {
+  let config = config.canonicalize().ok()?;
+  let relative = config.strip_prefix(root.canonicalize().ok()?).ok()?;
 
-  Some((username.to_string(), password.to_string()))
+  Some(glob::Pattern::escape(
+    &root.join(relative).to_string_lossy(),
+  ))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn normalize(args: &[&str]) -> Vec<String> {
+    normalize_args(args.iter().map(|arg| arg.to_string()).collect())
+  }
+
+  #[test]
+  fn spa_takes_an_optional_file() {
+    assert_eq!(normalize(&["--spa"]), ["--spa=index.html"]);
+    assert_eq!(
+      normalize(&["--spa", "-p", "80"]),
+      ["--spa=index.html", "-p", "80"]
+    );
+    assert_eq!(
+      normalize(&["site", "--spa", "app.html"]),
+      ["site", "--spa=app.html"]
+    );
+    assert_eq!(normalize(&["--spa=app.html"]), ["--spa=app.html"]);
+    // The served directory isn't a SPA fallback
+    assert_eq!(normalize(&["--spa", "src"]), ["--spa=index.html", "src"]);
+  }
+
+  #[test]
+  fn flags_can_be_turned_off() {
+    assert_eq!(
+      normalize(&["--root-index=false", "--open=true"]),
+      ["--open"]
+    );
+    // Only flags, options with values keep theirs
+    assert_eq!(normalize(&["--bind=true"]), ["--bind=true"]);
+  }
+
+  #[test]
+  fn finds_given_options() {
+    let args = [
+      "site",
+      "-e",
+      "*.md",
+      "-dp8081",
+      "--include=*.js",
+      "--spa",
+      "-o",
+    ]
+    .map(String::from)
+    .to_vec();
+    let given = given_options(&args);
+    let mut given: Vec<_> = given
+      .iter()
+      .map(String::as_str)
+      .filter(|option| !option.starts_with("--no-"))
+      .collect();
+    given.sort();
+
+    assert!(given_options(&["--no-compress".to_string()]).contains("--compress"));
+
+    assert_eq!(
+      given,
+      [
+        "--exclude",
+        "--include",
+        "--open",
+        "--port",
+        "--serve-directories",
+        "--spa"
+      ]
+    );
+  }
+
+  #[test]
+  fn excludes_the_config_file_anywhere_in_the_root() {
+    let root = std::env::temp_dir().join(format!("swerve-main-{}-config", std::process::id()));
+    std::fs::create_dir_all(root.join("sub")).unwrap();
+    std::fs::write(root.join("sub").join("swerve.toml"), "").unwrap();
+    std::fs::write(root.join("swerve.toml"), "").unwrap();
+
+    let exclude = config_exclude(&root, &root.join("sub").join("swerve.toml")).unwrap();
+    let pattern = glob::Pattern::new(&exclude).unwrap();
+
+    assert!(pattern.matches(&root.join("sub").join("swerve.toml").to_string_lossy()));
+    assert!(!pattern.matches(&root.join("swerve.toml").to_string_lossy()));
+    assert!(!pattern.matches(&root.join("sub").join("index.html").to_string_lossy()));
+
+    // A config outside of the served directory isn't served anyway
+    assert_eq!(
+      config_exclude(&root.join("sub"), &root.join("swerve.toml")),
+      None
+    );
+
+    let _ = std::fs::remove_dir_all(&root);
+  }
 }
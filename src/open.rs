@@ -1,6 +1,8 @@
 use std::process::Command;
 
 #[cfg(target_os = "windows")]
+// The browser process is intentionally left running after we return
+#[allow(clippy::zombie_processes)]
 pub fn open_in_browser(url: &str) {
   Command::new("cmd")
     .args(["/C", "start", url])
@@ -9,6 +11,8 @@ pub fn open_in_browser(url: &str) {
 }
 
 #[cfg(target_os = "macos")]
+// The browser process is intentionally left running after we return
+#[allow(clippy::zombie_processes)]
 pub fn open_in_browser(url: &str) {
   Command::new("open")
     .arg(url)
@@ -17,6 +21,8 @@ pub fn open_in_browser(url: &str) {
 }
 
 #[cfg(target_os = "linux")]
+// The browser process is intentionally left running after we return
+#[allow(clippy::zombie_processes)]
 pub fn open_in_browser(url: &str) {
   Command::new("xdg-open")
     .arg(url)
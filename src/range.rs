@@ -0,0 +1,219 @@
+use std::ops::Range;
+
+// Requests listing more ranges than this get the whole thing instead, so a single request can't
+// make the server send the same bytes over and over
+const MAX_RANGES: usize = 16;
+
+pub enum RangeRequest {
+  // No (usable) Range header, serve the whole thing
+  Full,
+  // One or more satisfiable ranges
+  Partial(Vec<Range<usize>>),
+  // A valid Range header where none of the ranges fit within the content
+  Unsatisfiable,
+}
+
+/// Parse a `Range` header value (eg. `bytes=0-499, -500`) against content of the given length.
+///
+/// Malformed headers and units other than `bytes` are ignored, as per RFC 9110. Overlapping and
+/// adjacent ranges are merged.
+pub fn parse_range(header: &str, len: usize) -> RangeRequest {
+  let specs = match header.trim().strip_prefix("bytes=") {
+    Some(specs) if specs.split(',').count() <= MAX_RANGES => specs,
+    _ => return RangeRequest::Full,
+  };
+
+  let mut ranges = Vec::new();
+
+  for spec in specs.split(',') {
+    let (start, end) = match spec.trim().split_once('-') {
+      Some(parts) => parts,
+      None => return RangeRequest::Full,
+    };
+
+    let range = match (start.trim(), end.trim()) {
+      // bytes=-500, the last 500 bytes
+      ("", suffix) => match suffix.parse::<usize>() {
+        Ok(0) => continue,
+        Ok(suffix) => len.saturating_sub(suffix)..len,
+        Err(_) => return RangeRequest::Full,
+      },
+      // bytes=500-, everything from byte 500 onwards
+      (start, "") => match start.parse::<usize>() {
+        Ok(start) => start..len,
+        Err(_) => return RangeRequest::Full,
+      },
+      // bytes=0-499
+      (start, end) => match (start.parse::<usize>(), end.parse::<usize>()) {
+        (Ok(start), Ok(end)) if start <= end => start..len.min(end.saturating_add(1)),
+        _ => return RangeRequest::Full,
+      },
+    };
+
+    // Unsatisfiable ranges are skipped, the rest can still be served
+    if range.start < len {
+      ranges.push(range);
+    }
+  }
+
+  if ranges.is_empty() {
+    RangeRequest::Unsatisfiable
+  } else {
+    RangeRequest::Partial(merge_ranges(ranges))
+  }
+}
+
+fn merge_ranges(mut ranges: Vec<Range<usize>>) -> Vec<Range<usize>> {
+  ranges.sort_by_key(|range| range.start);
+
+  let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+
+  for range in ranges {
+    match merged.last_mut() {
+      Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+      _ => merged.push(range),
+    }
+  }
+
+  merged
+}
+
+/// Whether an `If-Range` header value still matches the current representation.
+pub fn if_range_matches(header: &str, etag: &str, last_modified: &str) -> bool {
+  let header = header.trim();
+
+  // If-Range requires a strong comparison, so weak tags never match
+  if header.starts_with("W/") {
+    return false;
+  }
+
+  if header.starts_with('"') {
+    return header == etag;
+  }
+
+  header == last_modified
+}
+
+pub fn content_range(range: &Range<usize>, len: usize) -> String {
+  format!("bytes {}-{}/{}", range.start, range.end - 1, len)
+}
+
+/// Build a `multipart/byteranges` body out of the requested ranges, returning the boundary used.
+pub fn multipart_body(
+  content: &[u8],
+  ranges: &[Range<usize>],
+  content_type: &str,
+) -> (String, Vec<u8>) {
+  let boundary = format!(
+    "swerve-{:x}",
+    std::time::SystemTime::now()
+      .duration_since(std::time::UNIX_EPOCH)
+      .unwrap_or_default()
+      .as_nanos()
+  );
+  let mut body = Vec::new();
+
+  for range in ranges {
+    body.extend_from_slice(
+      format!(
+        "--{}\r\nContent-Type: {}\r\nContent-Range: {}\r\n\r\n",
+        boundary,
+        content_type,
+        content_range(range, content.len())
+      )
+      .as_bytes(),
+    );
+    body.extend_from_slice(&content[range.clone()]);
+    body.extend_from_slice(b"\r\n");
+  }
+
+  body.extend_from_slice(format!("--{}--\r\n", boundary).as_bytes());
+
+  (boundary, body)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // As (start, end) pairs, which are easier to compare than ranges
+  fn ranges(header: &str, len: usize) -> Vec<(usize, usize)> {
+    match parse_range(header, len) {
+      RangeRequest::Partial(ranges) => ranges
+        .iter()
+        .map(|range| (range.start, range.end))
+        .collect(),
+      _ => panic!("expected a partial range request for {:?}", header),
+    }
+  }
+
+  #[test]
+  fn merges_overlapping_and_adjacent_ranges() {
+    assert_eq!(ranges("bytes=0-,0-,0-", 100), [(0, 100)]);
+    assert_eq!(ranges("bytes=50-59,0-9,10-19", 100), [(0, 20), (50, 60)]);
+    assert_eq!(ranges("bytes=0-49,-60", 100), [(0, 100)]);
+  }
+
+  #[test]
+  fn too_many_ranges_serve_everything() {
+    let header = format!("bytes={}", vec!["0-"; MAX_RANGES + 1].join(","));
+
+    assert!(matches!(parse_range(&header, 100), RangeRequest::Full));
+  }
+
+  #[test]
+  fn parses_single_ranges() {
+    assert_eq!(ranges("bytes=0-9", 100), [(0, 10)]);
+    assert_eq!(ranges(" bytes=10- ", 100), [(10, 100)]);
+    // Suffix ranges count from the end, and can ask for more than there is
+    assert_eq!(ranges("bytes=-10", 100), [(90, 100)]);
+    assert_eq!(ranges("bytes=-500", 100), [(0, 100)]);
+    // Ends past the end of the content are clamped
+    assert_eq!(ranges("bytes=90-500", 100), [(90, 100)]);
+  }
+
+  #[test]
+  fn skips_unsatisfiable_ranges() {
+    assert_eq!(ranges("bytes=0-9,200-300", 100), [(0, 10)]);
+    assert!(matches!(
+      parse_range("bytes=100-", 100),
+      RangeRequest::Unsatisfiable
+    ));
+    assert!(matches!(
+      parse_range("bytes=200-300,-0", 100),
+      RangeRequest::Unsatisfiable
+    ));
+    assert!(matches!(
+      parse_range("bytes=0-", 0),
+      RangeRequest::Unsatisfiable
+    ));
+  }
+
+  #[test]
+  fn ignores_malformed_headers() {
+    for header in [
+      "items=0-9",
+      "bytes=9-0",
+      "bytes=a-b",
+      "bytes=0-9,x",
+      "bytes=5",
+      "bytes=--5",
+    ] {
+      assert!(
+        matches!(parse_range(header, 100), RangeRequest::Full),
+        "{}",
+        header
+      );
+    }
+  }
+
+  #[test]
+  fn if_range_compares_strongly() {
+    let date = "Sun, 18 Oct 2026 12:00:00 GMT";
+
+    assert!(if_range_matches("\"abc\"", "\"abc\"", date));
+    assert!(!if_range_matches("W/\"abc\"", "\"abc\"", date));
+    assert!(!if_range_matches("\"old\"", "\"abc\"", date));
+    assert!(if_range_matches(date, "\"abc\"", date));
+  }
+}
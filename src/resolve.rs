@@ -0,0 +1,197 @@
+use std::{
+  fs,
+  path::{Component, Path, PathBuf},
+  str::FromStr,
+};
+
+/// Whether symlinks inside the served directory are followed.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum SymlinkPolicy {
+  Never,
+  /// Only follow symlinks that lead somewhere inside the served directory
+  #[default]
+  WithinRoot,
+  Always,
+}
+
+impl FromStr for SymlinkPolicy {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "never" => Ok(SymlinkPolicy::Never),
+      "within-root" => Ok(SymlinkPolicy::WithinRoot),
+      "always" => Ok(SymlinkPolicy::Always),
+      _ => Err(format!(
+        "invalid symlink policy `{}`, expected never, within-root or always",
+        s
+      )),
+    }
+  }
+}
+
+/// Join a decoded URL path onto the root. Returns `None` if any segment would step outside
+/// of the root, eg. `..` or (on Windows) a drive prefix.
+pub fn join_url_path(root: &Path, url_path: &str) -> Option<PathBuf> {
+  let mut path = root.to_path_buf();
+
+  for segment in url_path.split('/') {
+    if segment.is_empty() || segment == "." {
+      continue;
+    }
+
+    let mut components = Path::new(segment).components();
+
+    match (components.next(), components.next()) {
+      (Some(Component::Normal(segment)), None) => path.push(segment),
+      _ => return None,
+    }
+  }
+
+  Some(path)
+}
+
+/// Whether the path may be served according to the symlink policy. Paths that
+/// don't exist are allowed, so they can 404 as usual.
+pub fn is_allowed(root: &Path, path: &Path, policy: SymlinkPolicy) -> bool {
+  if policy == SymlinkPolicy::Always {
+    return true;
+  }
+
+  let relative = match path.strip_prefix(root) {
+    Ok(relative) => relative,
+    Err(_) => return false,
+  };
+  let mut current = root.to_path_buf();
+  let mut deepest_existing = root.to_path_buf();
+  let mut has_symlink = false;
+
+  for component in relative.components() {
+    current.push(component);
+
+    match fs::symlink_metadata(&current) {
+      Ok(meta) => {
+        has_symlink |= meta.file_type().is_symlink();
+        deepest_existing = current.clone();
+      }
+      Err(_) => break,
+    }
+  }
+
+  if !has_symlink {
+    return true;
+  }
+
+  if policy == SymlinkPolicy::Never {
+    return false;
+  }
+
+  // Within root, so wherever the symlink(s) lead has to still be inside of the root
+  match (root.canonicalize(), deepest_existing.canonicalize()) {
+    (Ok(root), Ok(target)) => target.starts_with(root),
+    // Dangling symlink
+    _ => false,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::url::request_path;
+
+  fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("swerve-resolve-{}-{}", std::process::id(), name));
+
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+  }
+
+  #[test]
+  fn joins_paths_inside_the_root() {
+    let root = Path::new("/srv");
+
+    assert_eq!(join_url_path(root, "/"), Some(PathBuf::from("/srv")));
+    assert_eq!(
+      join_url_path(root, "/a/./b/"),
+      Some(PathBuf::from("/srv/a/b"))
+    );
+    assert_eq!(
+      join_url_path(root, "/a..b"),
+      Some(PathBuf::from("/srv/a..b"))
+    );
+  }
+
+  #[test]
+  fn rejects_traversal() {
+    let root = Path::new("/srv");
+
+    assert_eq!(join_url_path(root, "/../etc/passwd"), None);
+    assert_eq!(join_url_path(root, "/a/../../etc/passwd"), None);
+    assert_eq!(join_url_path(root, "/a/.."), None);
+
+    // Encoded dots and slashes decode into the same thing
+    for url in [
+      "/%2e%2e/etc/passwd",
+      "/a%2F..%2F..%2Fetc%2Fpasswd",
+      "/%2E%2E%2f",
+    ] {
+      assert_eq!(
+        join_url_path(root, &request_path(url).unwrap()),
+        None,
+        "{}",
+        url
+      );
+    }
+  }
+
+  #[cfg(windows)]
+  #[test]
+  fn rejects_drive_prefixes() {
+    assert_eq!(join_url_path(Path::new("C:\\srv"), "/C:/Windows"), None);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn follows_symlinks_by_policy() {
+    let outside = temp_dir("outside");
+    let root = temp_dir("root");
+
+    fs::write(outside.join("secret.txt"), "secret").unwrap();
+    fs::create_dir(root.join("dir")).unwrap();
+    fs::write(root.join("dir/file.txt"), "file").unwrap();
+    std::os::unix::fs::symlink(outside.join("secret.txt"), root.join("out.txt")).unwrap();
+    std::os::unix::fs::symlink(&outside, root.join("outdir")).unwrap();
+    std::os::unix::fs::symlink(root.join("dir"), root.join("indir")).unwrap();
+    std::os::unix::fs::symlink(root.join("missing"), root.join("dangling")).unwrap();
+
+    let allowed = |path: &str, policy| is_allowed(&root, &root.join(path), policy);
+
+    // Plain paths, and ones that don't exist, are always fine
+    for policy in [SymlinkPolicy::Never, SymlinkPolicy::WithinRoot] {
+      assert!(allowed("dir/file.txt", policy));
+      assert!(allowed("nothing/here.txt", policy));
+    }
+
+    assert!(!allowed("out.txt", SymlinkPolicy::Never));
+    assert!(!allowed("indir/file.txt", SymlinkPolicy::Never));
+
+    assert!(!allowed("out.txt", SymlinkPolicy::WithinRoot));
+    assert!(!allowed("outdir/secret.txt", SymlinkPolicy::WithinRoot));
+    assert!(allowed("indir/file.txt", SymlinkPolicy::WithinRoot));
+    assert!(!allowed("dangling", SymlinkPolicy::WithinRoot));
+
+    assert!(allowed("out.txt", SymlinkPolicy::Always));
+    assert!(allowed("outdir/secret.txt", SymlinkPolicy::Always));
+
+    // Paths outside of the root aren't allowed to begin with
+    assert!(!is_allowed(
+      &root,
+      &outside.join("secret.txt"),
+      SymlinkPolicy::Never
+    ));
+
+    let _ = fs::remove_dir_all(outside);
+    let _ = fs::remove_dir_all(root);
+  }
+}
@@ -0,0 +1,386 @@
+#[cfg(feature = "hotreload")]
+use std::time::Duration;
+use std::{error::Error, net::SocketAddr, path::PathBuf, sync::Arc, thread::JoinHandle};
+
+use threadpool::ThreadPool;
+#[cfg(feature = "tls")]
+use tiny_http::SslConfig;
+
+use crate::{globs::Globs, handler, resolve::SymlinkPolicy};
+#[cfg(feature = "hotreload")]
+use crate::{
+  globs::WatchIgnores,
+  hotreload::{self, WatchOptions},
+};
+
+/// Everything request handlers need to know about how to serve files, shared between threads.
+pub(crate) struct ServerConfig {
+  pub root: PathBuf,
+  pub root_index: bool,
+  pub serve_directories: bool,
+  pub globs: Globs,
+  pub credentials: Option<(String, String)>,
+  pub spa: Option<String>,
+  #[cfg_attr(not(feature = "compression"), allow(dead_code))]
+  pub compress: bool,
+  pub follow_symlinks: SymlinkPolicy,
+  /// Hub to register hot reload clients with, if hot reloading is enabled
+  #[cfg(feature = "hotreload")]
+  pub hot_reload: Option<hotreload::Hub>,
+  #[cfg(feature = "hotreload")]
+  pub reload_indicator: bool,
+}
+
+/// Configures and starts a server.
+///
+/// ```no_run
+/// let server = swerve::ServerBuilder::new("dist")
+///   .port(0)
+///   .threads(4)
+///   .start()
+///   .expect("Failed to start server");
+///
+/// println!("Listening on {}", server.addr());
+/// server.shutdown();
+/// ```
+pub struct ServerBuilder {
+  root: PathBuf,
+  bind: String,
+  port: u16,
+  threads: usize,
+  includes: Vec<String>,
+  excludes: Vec<String>,
+  credentials: Option<(String, String)>,
+  root_index: bool,
+  serve_directories: bool,
+  spa: Option<String>,
+  compress: bool,
+  follow_symlinks: SymlinkPolicy,
+  #[cfg(feature = "tls")]
+  ssl: Option<SslConfig>,
+  #[cfg(feature = "hotreload")]
+  hot_reload: bool,
+  #[cfg(feature = "hotreload")]
+  reload_debounce: Duration,
+  #[cfg(feature = "hotreload")]
+  watch_ignores: Vec<String>,
+  #[cfg(feature = "hotreload")]
+  on_change: Option<String>,
+  #[cfg(feature = "hotreload")]
+  watch_paths: Vec<PathBuf>,
+  #[cfg(feature = "hotreload")]
+  reload_indicator: bool,
+}
+
+impl ServerBuilder {
+  pub fn new(root: impl Into<PathBuf>) -> Self {
+    ServerBuilder {
+      root: root.into(),
+      bind: "127.0.0.1".to_string(),
+      port: 8080,
+      threads: 1,
+      includes: Vec::new(),
+      excludes: Vec::new(),
+      credentials: None,
+      root_index: false,
+      serve_directories: false,
+      spa: None,
+      compress: true,
+      follow_symlinks: SymlinkPolicy::default(),
+      #[cfg(feature = "tls")]
+      ssl: None,
+      #[cfg(feature = "hotreload")]
+      hot_reload: false,
+      #[cfg(feature = "hotreload")]
+      reload_debounce: Duration::from_millis(100),
+      #[cfg(feature = "hotreload")]
+      watch_ignores: Vec::new(),
+      #[cfg(feature = "hotreload")]
+      on_change: None,
+      #[cfg(feature = "hotreload")]
+      watch_paths: Vec::new(),
+      #[cfg(feature = "hotreload")]
+      reload_indicator: true,
+    }
+  }
+
+  /// Address to bind to, `127.0.0.1` by default.
+  pub fn bind(mut self, bind: impl Into<String>) -> Self {
+    self.bind = bind.into();
+    self
+  }
+
+  /// Port to listen on, `8080` by default. Use `0` to pick any free port.
+  pub fn port(mut self, port: u16) -> Self {
+    self.port = port;
+    self
+  }
+
+  /// Amount of threads to serve requests with.
+  pub fn threads(mut self, threads: usize) -> Self {
+    self.threads = threads.max(1);
+    self
+  }
+
+  /// Only serve files matching this glob pattern. Can be called multiple times.
+  pub fn include(mut self, glob: impl Into<String>) -> Self {
+    self.includes.push(glob.into());
+    self
+  }
+
+  /// Never serve files matching this glob pattern. Can be called multiple times.
+  pub fn exclude(mut self, glob: impl Into<String>) -> Self {
+    self.excludes.push(glob.into());
+    self
+  }
+
+  /// Require these basic auth credentials for every request.
+  pub fn basic_auth(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+    self.credentials = Some((username.into(), password.into()));
+    self
+  }
+
+  /// Serve index.html or index.htm when a directory containing one is requested.
+  pub fn root_index(mut self, root_index: bool) -> Self {
+    self.root_index = root_index;
+    self
+  }
+
+  /// Serve an HTML listing when a directory is requested.
+  pub fn serve_directories(mut self, serve_directories: bool) -> Self {
+    self.serve_directories = serve_directories;
+    self
+  }
+
+  /// Serve this file (relative to the root) for extensionless routes that don't exist.
+  pub fn spa(mut self, file: impl Into<String>) -> Self {
+    self.spa = Some(file.into());
+    self
+  }
+
+  /// Compress responses on the fly when the client supports it. Enabled by default.
+  pub fn compress(mut self, compress: bool) -> Self {
+    self.compress = compress;
+    self
+  }
+
+  /// Whether to follow symlinks in the root, [`SymlinkPolicy::WithinRoot`] by default.
+  pub fn follow_symlinks(mut self, policy: SymlinkPolicy) -> Self {
+    self.follow_symlinks = policy;
+    self
+  }
+
+  /// Serve over HTTPS.
+  #[cfg(feature = "tls")]
+  pub fn tls(mut self, ssl: SslConfig) -> Self {
+    self.ssl = Some(ssl);
+    self
+  }
+
+  /// Watch the root for changes and reload pages that are open in the browser.
+  #[cfg(feature = "hotreload")]
+  pub fn hot_reload(mut self, hot_reload: bool) -> Self {
+    self.hot_reload = hot_reload;
+    self
+  }
+
+  /// How long to wait for more changes before reloading, so a burst of them only reloads once. 100ms by default.
+  #[cfg(feature = "hotreload")]
+  pub fn reload_debounce(mut self, debounce: Duration) -> Self {
+    self.reload_debounce = debounce;
+    self
+  }
+
+  /// Don't reload for changes to paths matching this glob pattern, on top of the default ignore
+  /// list (node_modules, .git, editor swap files...). Patterns starting with one of the watched
+  /// directories (eg. `src/generated/**`) only apply to that directory. Can be called multiple times.
+  #[cfg(feature = "hotreload")]
+  pub fn watch_ignore(mut self, glob: impl Into<String>) -> Self {
+    self.watch_ignores.push(glob.into());
+    self
+  }
+
+  /// Run this shell command when files change, and only reload once it finishes. If it fails,
+  /// pages are sent the error instead. Enables hot reloading.
+  #[cfg(feature = "hotreload")]
+  pub fn on_change(mut self, command: impl Into<String>) -> Self {
+    self.on_change = Some(command.into());
+    self.hot_reload = true;
+    self
+  }
+
+  /// Also watch this directory for changes, eg. sources that `on_change` builds into the served
+  /// directory. Can be called multiple times. Enables hot reloading.
+  #[cfg(feature = "hotreload")]
+  pub fn watch(mut self, path: impl Into<PathBuf>) -> Self {
+    self.watch_paths.push(path.into());
+    self.hot_reload = true;
+    self
+  }
+
+  /// Show a small dot on pages telling whether hot reloading is connected. Enabled by default.
+  #[cfg(feature = "hotreload")]
+  pub fn reload_indicator(mut self, reload_indicator: bool) -> Self {
+    self.reload_indicator = reload_indicator;
+    self
+  }
+
+  /// Bind the server and start serving requests in the background.
+  pub fn start(self) -> Result<ServerHandle, Box<dyn Error + Send + Sync + 'static>> {
+    let globs = Globs::new(&self.includes, &self.excludes)?;
+    let server_addr = format!("{}:{}", self.bind, self.port);
+
+    #[cfg(feature = "tls")]
+    let secure = self.ssl.is_some();
+    #[cfg(not(feature = "tls"))]
+    let secure = false;
+
+    #[cfg(feature = "tls")]
+    let server = match self.ssl {
+      Some(ssl) => tiny_http::Server::https(server_addr, ssl)?,
+      None => tiny_http::Server::http(server_addr)?,
+    };
+    #[cfg(not(feature = "tls"))]
+    let server = tiny_http::Server::http(server_addr)?;
+    let server = Arc::new(server);
+    let addr = server
+      .server_addr()
+      .to_ip()
+      .ok_or("Server is not listening on an IP address")?;
+
+    #[cfg(feature = "hotreload")]
+    let hot_reload = match self.hot_reload {
+      true => Some(HotReload::start(
+        &self.root,
+        WatchOptions {
+          globs: globs.clone(),
+          ignores: WatchIgnores::new(&self.watch_ignores)?,
+          debounce: self.reload_debounce,
+          on_change: self.on_change,
+          extra_paths: self.watch_paths,
+        },
+      )?),
+      false => None,
+    };
+
+    let config = Arc::new(ServerConfig {
+      root: self.root,
+      root_index: self.root_index,
+      serve_directories: self.serve_directories,
+      globs,
+      credentials: self.credentials,
+      spa: self.spa,
+      compress: self.compress,
+      follow_symlinks: self.follow_symlinks,
+      #[cfg(feature = "hotreload")]
+      hot_reload: hot_reload.as_ref().map(|hot_reload| hot_reload.hub.clone()),
+      #[cfg(feature = "hotreload")]
+      reload_indicator: self.reload_indicator,
+    });
+
+    let pool = ThreadPool::new(self.threads);
+    let thread = {
+      let server = server.clone();
+
+      std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+          let config = config.clone();
+          pool.execute(move || handler::handle_request(request, &config));
+        }
+
+        // Let in-flight requests finish
+        pool.join();
+      })
+    };
+
+    Ok(ServerHandle {
+      addr,
+      secure,
+      server,
+      thread: Some(thread),
+      #[cfg(feature = "hotreload")]
+      hot_reload,
+    })
+  }
+}
+
+/// A running server. The server is shut down when this is dropped.
+pub struct ServerHandle {
+  addr: SocketAddr,
+  secure: bool,
+  server: Arc<tiny_http::Server>,
+  thread: Option<JoinHandle<()>>,
+  #[cfg(feature = "hotreload")]
+  hot_reload: Option<HotReload>,
+}
+
+impl ServerHandle {
+  /// The address the server is bound to, including the actual port if `0` was used.
+  pub fn addr(&self) -> SocketAddr {
+    self.addr
+  }
+
+  /// Whether the server is serving over HTTPS.
+  pub fn is_secure(&self) -> bool {
+    self.secure
+  }
+
+  /// Block until the server stops.
+  pub fn join(mut self) {
+    if let Some(thread) = self.thread.take() {
+      thread.join().unwrap_or_default();
+    }
+  }
+
+  /// Stop accepting requests, wait for in-flight ones to finish and stop hot reloading.
+  pub fn shutdown(mut self) {
+    self.stop();
+  }
+
+  fn stop(&mut self) {
+    self.server.unblock();
+
+    if let Some(thread) = self.thread.take() {
+      thread.join().unwrap_or_default();
+    }
+
+    #[cfg(feature = "hotreload")]
+    if let Some(hot_reload) = self.hot_reload.take() {
+      hot_reload.stop();
+    }
+  }
+}
+
+impl Drop for ServerHandle {
+  fn drop(&mut self) {
+    self.stop();
+  }
+}
+
+#[cfg(feature = "hotreload")]
+struct HotReload {
+  // Watching stops when the watcher is dropped
+  _watcher: notify::RecommendedWatcher,
+  hub: hotreload::Hub,
+}
+
+#[cfg(feature = "hotreload")]
+impl HotReload {
+  fn start(
+    root: &std::path::Path,
+    options: WatchOptions,
+  ) -> Result<Self, Box<dyn Error + Send + Sync>> {
+    let hub = hotreload::Hub::default();
+    let watcher = hotreload::watch(root, options, hub.clone())?;
+
+    Ok(HotReload {
+      _watcher: watcher,
+      hub,
+    })
+  }
+
+  fn stop(self) {
+    // Clients disconnect once they are unregistered
+    self.hub.close();
+  }
+}
@@ -0,0 +1,175 @@
+use std::{
+  fs, io,
+  net::{IpAddr, Ipv4Addr, Ipv6Addr},
+  path::{Path, PathBuf},
+};
+
+use tiny_http::SslConfig;
+
+use crate::{log, warn};
+
+/// Load a certificate and private key (both PEM encoded) from disk.
+pub fn load_ssl_config(cert: &Path, key: &Path) -> Result<SslConfig, String> {
+  let certificate =
+    fs::read(cert).map_err(|e| format!("Failed to read certificate {:?}: {}", cert, e))?;
+  let private_key =
+    fs::read(key).map_err(|e| format!("Failed to read private key {:?}: {}", key, e))?;
+
+  Ok(SslConfig {
+    certificate,
+    private_key,
+  })
+}
+
+/// Get a self-signed certificate covering localhost, the LAN IP and the bound address.
+///
+/// Certificates are cached so browsers only need to trust them once. A new one is only
+/// generated when the set of names it has to cover changes (eg. when the LAN IP does).
+pub fn self_signed_ssl_config(bind: &str) -> Result<SslConfig, String> {
+  let mut names = vec![
+    "localhost".to_string(),
+    IpAddr::V4(Ipv4Addr::LOCALHOST).to_string(),
+    IpAddr::V6(Ipv6Addr::LOCALHOST).to_string(),
+  ];
+
+  if let Ok(ip) = local_ip_address::local_ip() {
+    names.push(ip.to_string());
+  }
+
+  if bind != "0.0.0.0" && !names.iter().any(|name| name == bind) {
+    names.push(bind.to_string());
+  }
+
+  let dir = cache_dir();
+  let cert_path = dir.join("cert.pem");
+  let key_path = dir.join("key.pem");
+  let names_path = dir.join("names.txt");
+
+  let mut cache = true;
+
+  if fs::read_to_string(&names_path).is_ok_and(|cached| cached == names.join("\n")) {
+    if let Ok(config) = load_ssl_config(&cert_path, &key_path) {
+      // Older versions cached the key with default permissions. A cache that someone else owns
+      // (eg. in a shared temp dir) can't be trusted, they could've put a certificate of their own in it
+      match make_private(&dir, 0o700).and_then(|_| make_private(&key_path, 0o600)) {
+        Ok(_) => {
+          log!("Using cached self-signed certificate from {:?}", dir);
+          return Ok(config);
+        }
+        Err(e) => {
+          warn!(
+            "Not using the cached self-signed certificate in {:?}: {}",
+            dir, e
+          );
+          cache = false;
+        }
+      }
+    }
+  }
+
+  log!(
+    "Generating self-signed certificate for {}",
+    names.join(", ")
+  );
+
+  let generated = rcgen::generate_simple_self_signed(names.clone())
+    .map_err(|e| format!("Failed to generate certificate: {}", e))?;
+  let certificate = generated.cert.pem();
+  let private_key = generated.key_pair.serialize_pem();
+
+  // Not being able to cache the certificate isn't fatal, it just means a new one next time
+  if cache {
+    let cached = create_private_dir(&dir)
+      .and_then(|_| fs::write(&cert_path, &certificate))
+      .and_then(|_| write_private_file(&key_path, private_key.as_bytes()))
+      .and_then(|_| fs::write(&names_path, names.join("\n")));
+
+    match cached {
+      Ok(_) => log!("Cached self-signed certificate in {:?}", dir),
+      Err(e) => warn!("Failed to cache self-signed certificate: {:?}", e),
+    }
+  }
+
+  Ok(SslConfig {
+    certificate: certificate.into_bytes(),
+    private_key: private_key.into_bytes(),
+  })
+}
+
+// Browsers get told to trust the certificate for good, so only the current user may read its key
+fn create_private_dir(dir: &Path) -> io::Result<()> {
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::DirBuilderExt;
+
+    fs::DirBuilder::new()
+      .recursive(true)
+      .mode(0o700)
+      .create(dir)?;
+  }
+
+  #[cfg(not(unix))]
+  fs::create_dir_all(dir)?;
+
+  make_private(dir, 0o700)
+}
+
+fn write_private_file(path: &Path, content: &[u8]) -> io::Result<()> {
+  #[cfg(unix)]
+  {
+    use std::{io::Write, os::unix::fs::OpenOptionsExt};
+
+    let mut file = fs::OpenOptions::new()
+      .write(true)
+      .create(true)
+      .truncate(true)
+      .mode(0o600)
+      .open(path)?;
+
+    // The mode only applies to new files, not to one left behind with wider permissions
+    make_private(path, 0o600)?;
+    file.write_all(content)
+  }
+
+  #[cfg(not(unix))]
+  fs::write(path, content)
+}
+
+/// Make sure only the current user has access to the path, which also has to be theirs.
+#[cfg_attr(not(unix), allow(unused_variables))]
+fn make_private(path: &Path, mode: u32) -> io::Result<()> {
+  #[cfg(unix)]
+  {
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    // SAFETY: geteuid has no preconditions and can't fail
+    let uid = unsafe { libc::geteuid() };
+
+    // Not following symlinks, or one could point anywhere
+    if fs::symlink_metadata(path)?.uid() != uid {
+      return Err(io::Error::new(
+        io::ErrorKind::PermissionDenied,
+        format!("{:?} belongs to another user", path),
+      ));
+    }
+
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+  }
+
+  Ok(())
+}
+
+fn cache_dir() -> PathBuf {
+  #[cfg(target_os = "windows")]
+  let base = std::env::var_os("LOCALAPPDATA").map(PathBuf::from);
+
+  #[cfg(target_os = "macos")]
+  let base = std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Caches"));
+
+  #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+  let base = std::env::var_os("XDG_CACHE_HOME")
+    .map(PathBuf::from)
+    .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")));
+
+  base.unwrap_or_else(std::env::temp_dir).join("swerve")
+}
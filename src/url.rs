@@ -0,0 +1,83 @@
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+// Everything but unreserved characters (RFC 3986) gets encoded in path segments
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+  .remove(b'-')
+  .remove(b'.')
+  .remove(b'_')
+  .remove(b'~');
+
+/// Split a request URL into its (still encoded) path and query string, dropping any fragment.
+pub fn split_url(url: &str) -> (&str, Option<&str>) {
+  let url = url.split('#').next().unwrap_or_default();
+
+  match url.split_once('?') {
+    Some((path, query)) => (path, Some(query)),
+    None => (url, None),
+  }
+}
+
+/// Get the decoded path of a request URL, with the query string and fragment stripped and
+/// repeated slashes collapsed. Returns `None` if the path doesn't decode to valid UTF-8.
+pub fn request_path(url: &str) -> Option<String> {
+  let (path, _) = split_url(url);
+  let path = percent_decode_str(path).decode_utf8().ok()?;
+  let mut normalized = String::with_capacity(path.len());
+
+  for c in path.chars() {
+    if c == '/' && normalized.ends_with('/') {
+      continue;
+    }
+
+    normalized.push(c);
+  }
+
+  if !normalized.starts_with('/') {
+    normalized.insert(0, '/');
+  }
+
+  Some(normalized)
+}
+
+/// Percent-encode a path, leaving the slashes between segments intact.
+pub fn encode_path(path: &str) -> String {
+  path
+    .split('/')
+    .map(|segment| utf8_percent_encode(segment, PATH_SEGMENT).to_string())
+    .collect::<Vec<_>>()
+    .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn request_path_decodes_and_strips_the_query() {
+    assert_eq!(request_path("/a%20b.html?x=1#top").unwrap(), "/a b.html");
+    assert_eq!(request_path("/caf%C3%A9/").unwrap(), "/café/");
+    assert_eq!(request_path("//a///b").unwrap(), "/a/b");
+    assert_eq!(request_path("").unwrap(), "/");
+    assert_eq!(request_path("?x").unwrap(), "/");
+  }
+
+  #[test]
+  fn request_path_decodes_dots_and_slashes() {
+    // These are only decoded here, rejecting them is up to resolve::join_url_path
+    assert_eq!(request_path("/%2e%2e/secret").unwrap(), "/../secret");
+    assert_eq!(
+      request_path("/a%2F..%2F..%2Fsecret").unwrap(),
+      "/a/../../secret"
+    );
+  }
+
+  #[test]
+  fn request_path_rejects_invalid_utf8() {
+    assert_eq!(request_path("/%FF"), None);
+  }
+
+  #[test]
+  fn encode_path_keeps_slashes() {
+    assert_eq!(encode_path("/a b/c#d.txt"), "/a%20b/c%23d.txt");
+  }
+}
@@ -0,0 +1,75 @@
+#![cfg(all(feature = "hotreload", unix))]
+
+use std::{fs, net::TcpStream, path::PathBuf, time::Duration};
+
+use swerve::{hotreload::WS_PATH, ServerBuilder, ServerHandle};
+use tungstenite::{Message, WebSocket};
+
+fn temp_dir(name: &str) -> PathBuf {
+  let dir = std::env::temp_dir().join(format!("swerve-test-{}-{}", std::process::id(), name));
+
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(&dir).expect("Failed to create temp dir");
+  dir
+}
+
+// Well short of the ping interval, so anything read came in without waiting for a ping
+fn connect(server: &ServerHandle) -> WebSocket<TcpStream> {
+  let stream = TcpStream::connect(server.addr()).expect("Failed to connect");
+  stream
+    .set_read_timeout(Some(Duration::from_secs(2)))
+    .unwrap();
+
+  let url = format!("ws://{}{}", server.addr(), WS_PATH);
+  let (websocket, _) = tungstenite::client(url, stream).expect("Failed to upgrade");
+  websocket
+}
+
+#[test]
+fn close_frames_are_answered() {
+  let root = temp_dir("hotreload-close");
+  let server = ServerBuilder::new(&root)
+    .port(0)
+    .hot_reload(true)
+    .start()
+    .expect("Failed to start server");
+  let mut websocket = connect(&server);
+
+  websocket.close(None).unwrap();
+
+  loop {
+    match websocket.read() {
+      Ok(_) => {}
+      Err(tungstenite::Error::ConnectionClosed) => break,
+      Err(e) => panic!("Close wasn't answered: {}", e),
+    }
+  }
+
+  server.shutdown();
+  let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn changes_are_sent_to_clients() {
+  let root = temp_dir("hotreload-changes");
+  let server = ServerBuilder::new(&root)
+    .port(0)
+    .hot_reload(true)
+    .start()
+    .expect("Failed to start server");
+  let mut websocket = connect(&server);
+
+  fs::write(root.join("index.html"), "<p>changed</p>").unwrap();
+
+  match websocket.read() {
+    Ok(Message::Text(text)) => assert!(
+      text.as_str().contains(r#""paths":["index.html"]"#),
+      "{}",
+      text
+    ),
+    other => panic!("Expected a change event, got {:?}", other),
+  }
+
+  server.shutdown();
+  let _ = fs::remove_dir_all(root);
+}
@@ -0,0 +1,72 @@
+use std::{
+  fs,
+  io::{Read, Write},
+  net::TcpStream,
+  path::PathBuf,
+};
+
+use swerve::{ServerBuilder, SymlinkPolicy};
+
+fn temp_dir(name: &str) -> PathBuf {
+  let dir = std::env::temp_dir().join(format!("swerve-test-{}-{}", std::process::id(), name));
+
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(&dir).expect("Failed to create temp dir");
+  dir
+}
+
+fn get_gzip(builder: ServerBuilder, path: &str) -> String {
+  let server = builder.port(0).start().expect("Failed to start server");
+  let mut stream = TcpStream::connect(server.addr()).expect("Failed to connect");
+
+  write!(
+    stream,
+    "GET {} HTTP/1.1\r\nHost: localhost\r\nAccept-Encoding: gzip\r\nConnection: close\r\n\r\n",
+    path
+  )
+  .unwrap();
+
+  let mut response = Vec::new();
+  stream.read_to_end(&mut response).unwrap();
+  server.shutdown();
+
+  String::from_utf8_lossy(&response).into_owned()
+}
+
+// A sidecar has to be servable on its own, or it would be a way around the symlink policy
+#[cfg(unix)]
+#[test]
+fn symlinked_sidecars_follow_the_symlink_policy() {
+  let outside = temp_dir("sidecar-outside");
+  let root = temp_dir("sidecar-root");
+
+  fs::write(outside.join("secret.txt"), "secret").unwrap();
+  fs::write(root.join("app.js"), "console.log('app')").unwrap();
+  std::os::unix::fs::symlink(outside.join("secret.txt"), root.join("app.js.gz")).unwrap();
+
+  for policy in [SymlinkPolicy::Never, SymlinkPolicy::WithinRoot] {
+    let response = get_gzip(ServerBuilder::new(&root).follow_symlinks(policy), "/app.js");
+
+    assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+    assert!(!response.contains("secret"), "{:?}: {}", policy, response);
+    assert!(response.contains("console.log('app')"), "{}", response);
+  }
+
+  let _ = fs::remove_dir_all(outside);
+  let _ = fs::remove_dir_all(root);
+}
+
+#[test]
+fn excluded_sidecars_are_not_served() {
+  let root = temp_dir("sidecar-excluded");
+
+  fs::write(root.join("app.js"), "console.log('app')").unwrap();
+  fs::write(root.join("app.js.gz"), "excluded").unwrap();
+
+  let response = get_gzip(ServerBuilder::new(&root).exclude("*.gz"), "/app.js");
+
+  assert!(!response.contains("excluded"), "{}", response);
+  assert!(response.contains("console.log('app')"), "{}", response);
+
+  let _ = fs::remove_dir_all(root);
+}